@@ -1,11 +1,20 @@
+pub mod block;
+pub mod email_verification;
+pub mod export;
 pub mod file;
+pub mod follow;
 pub mod note;
 pub mod note_files;
+pub mod note_revision;
 pub mod note_tags;
+pub mod oidc_identity;
+pub mod password_reset;
 pub mod question;
 pub mod quiz;
+pub mod recovery_code;
 pub mod save;
 pub mod tag;
 pub mod token;
 pub mod upvote;
 pub mod user;
+pub mod username_reservation;
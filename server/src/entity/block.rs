@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+use sea_orm::entity::prelude::*;
+use utoipa::ToSchema;
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    EnumIter,
+    DeriveActiveEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    ToSchema,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    /// Hides their notes from you
+    #[sea_orm(string_value = "mute")]
+    Mute,
+    /// Also hides your notes from them and stops them from interacting with them
+    #[sea_orm(string_value = "block")]
+    Block,
+}
+
+/// A user muting or blocking someone
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "blocks")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+
+    #[sea_orm(unique_key = "user_target")]
+    pub user_id: i32,
+    #[sea_orm(
+        belongs_to,
+        relation_enum = "User",
+        from = "user_id",
+        to = "id",
+        on_delete = "Cascade"
+    )]
+    pub user: HasOne<super::user::Entity>,
+
+    #[sea_orm(indexed, unique_key = "user_target")]
+    pub target_id: i32,
+    #[sea_orm(
+        belongs_to,
+        relation_enum = "Target",
+        from = "target_id",
+        to = "id",
+        on_delete = "Cascade"
+    )]
+    pub target: HasOne<super::user::Entity>,
+
+    pub kind: BlockKind,
+
+    pub created_at: DateTime<Utc>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
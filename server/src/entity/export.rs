@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use sea_orm::entity::prelude::*;
+use utoipa::ToSchema;
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    EnumIter,
+    DeriveActiveEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    ToSchema,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "snake_case")]
+pub enum ExportStatus {
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    #[sea_orm(string_value = "ready")]
+    Ready,
+    #[sea_orm(string_value = "failed")]
+    Failed,
+}
+
+/// A personal data export, built in the background
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "exports")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+
+    #[sea_orm(indexed)]
+    pub user_id: i32,
+    #[sea_orm(belongs_to, from = "user_id", to = "id", on_delete = "Cascade")]
+    pub user: HasOne<super::user::Entity>,
+
+    pub status: ExportStatus,
+
+    pub created_at: DateTime<Utc>,
+
+    pub finished_at: Option<DateTime<Utc>>,
+
+    /// Hash of the token that lets anyone download the archive once it's ready, until
+    /// `expires_at`
+    #[sea_orm(indexed, unique)]
+    pub download_token_hash: Option<String>,
+
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Where the ZIP archive is kept in storage, until the download link expires
+    pub storage_key: Option<String>,
+
+    /// Size of the archive, in bytes
+    pub size: Option<i64>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
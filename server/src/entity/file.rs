@@ -12,7 +12,7 @@ pub struct Model {
     #[sea_orm(indexed)]
     pub user_id: i32,
     #[schema(value_type = ())]
-    #[sea_orm(belongs_to, from = "user_id", to = "id")]
+    #[sea_orm(belongs_to, from = "user_id", to = "id", on_delete = "Cascade")]
     pub user: HasOne<super::user::Entity>,
 
     #[sea_orm(has_many, via = "note_files")]
@@ -25,7 +25,14 @@ pub struct Model {
 
     pub ocr: Option<String>,
 
-    pub data: Vec<u8>,
+    /// Where the contents are kept in storage
+    pub storage_key: String,
+
+    /// Size of the contents, in bytes
+    pub size: i64,
+
+    /// MIME type of the contents
+    pub content_type: String,
 }
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use sea_orm::entity::prelude::*;
+
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "follows")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+
+    #[sea_orm(unique_key = "follower_followee")]
+    pub follower_id: i32,
+    #[sea_orm(
+        belongs_to,
+        relation_enum = "Follower",
+        from = "follower_id",
+        to = "id",
+        on_delete = "Cascade"
+    )]
+    pub follower: HasOne<super::user::Entity>,
+
+    #[sea_orm(indexed, unique_key = "follower_followee")]
+    pub followee_id: i32,
+    #[sea_orm(
+        belongs_to,
+        relation_enum = "Followee",
+        from = "followee_id",
+        to = "id",
+        on_delete = "Cascade"
+    )]
+    pub followee: HasOne<super::user::Entity>,
+
+    pub created_at: DateTime<Utc>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
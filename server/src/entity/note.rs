@@ -10,7 +10,7 @@ pub struct Model {
 
     #[sea_orm(indexed)]
     pub user_id: i32,
-    #[sea_orm(belongs_to, from = "user_id", to = "id")]
+    #[sea_orm(belongs_to, from = "user_id", to = "id", on_delete = "Cascade")]
     pub user: HasOne<super::user::Entity>,
 
     pub created_at: DateTime<Utc>,
@@ -21,6 +21,12 @@ pub struct Model {
 
     pub public: bool,
 
+    /// Set when a moderator hides the note from everyone but its owner
+    pub hidden_at: Option<DateTime<Utc>>,
+
+    /// Set when the owner moves the note to the trash, it's purged for good later
+    pub deleted_at: Option<DateTime<Utc>>,
+
     #[sea_orm(has_many, via = "note_tags")]
     pub tags: HasMany<super::tag::Entity>,
 
@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use sea_orm::entity::prelude::*;
+
+/// A note's title, content and visibility as they were after one change
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "note_revisions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+
+    #[sea_orm(indexed)]
+    pub note_id: i32,
+    #[sea_orm(belongs_to, from = "note_id", to = "id", on_delete = "Cascade")]
+    pub note: HasOne<super::note::Entity>,
+
+    /// Who made the change
+    pub author_id: i32,
+    #[sea_orm(belongs_to, from = "author_id", to = "id", on_delete = "Cascade")]
+    pub author: HasOne<super::user::Entity>,
+
+    pub created_at: DateTime<Utc>,
+
+    pub title: String,
+
+    pub content: String,
+
+    pub public: bool,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -8,9 +8,9 @@ pub struct Model {
     pub note_id: i32,
     #[sea_orm(primary_key, auto_increment = false)]
     pub tag_id: i32,
-    #[sea_orm(belongs_to, from = "note_id", to = "id")]
+    #[sea_orm(belongs_to, from = "note_id", to = "id", on_delete = "Cascade")]
     pub note: Option<super::note::Entity>,
-    #[sea_orm(belongs_to, from = "tag_id", to = "id")]
+    #[sea_orm(belongs_to, from = "tag_id", to = "id", on_delete = "Cascade")]
     pub tag: Option<super::tag::Entity>,
 }
 
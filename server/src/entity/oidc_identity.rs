@@ -0,0 +1,28 @@
+use chrono::{DateTime, Utc};
+use sea_orm::entity::prelude::*;
+
+/// Links an account at an OpenID Connect provider to a local user
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "oidc_identities")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+
+    #[sea_orm(indexed)]
+    pub user_id: i32,
+    #[sea_orm(belongs_to, from = "user_id", to = "id", on_delete = "Cascade")]
+    pub user: HasOne<super::user::Entity>,
+
+    /// Key of the provider in the settings
+    #[sea_orm(unique_key = "provider_subject")]
+    pub provider: String,
+
+    /// The provider's stable ID for the account (`sub` claim)
+    #[sea_orm(unique_key = "provider_subject")]
+    pub subject: String,
+
+    pub created_at: DateTime<Utc>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
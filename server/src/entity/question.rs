@@ -8,7 +8,7 @@ pub struct Model {
     pub id: i32,
 
     pub quiz_id: i32,
-    #[sea_orm(belongs_to, from = "quiz_id", to = "id")]
+    #[sea_orm(belongs_to, from = "quiz_id", to = "id", on_delete = "Cascade")]
     pub quiz: HasOne<super::quiz::Entity>,
 
     pub title: String,
@@ -12,7 +12,7 @@ pub struct Model {
 
     #[sea_orm(unique)]
     pub note_id: i32,
-    #[sea_orm(belongs_to, from = "note_id", to = "id")]
+    #[sea_orm(belongs_to, from = "note_id", to = "id", on_delete = "Cascade")]
     pub note: HasOne<super::note::Entity>,
 }
 
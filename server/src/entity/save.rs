@@ -10,12 +10,12 @@ pub struct Model {
 
     #[sea_orm(unique_key = "user_note")]
     pub user_id: i32,
-    #[sea_orm(belongs_to, from = "user_id", to = "id")]
+    #[sea_orm(belongs_to, from = "user_id", to = "id", on_delete = "Cascade")]
     pub user: HasOne<super::user::Entity>,
 
     #[sea_orm(unique_key = "user_note")]
     pub note_id: i32,
-    #[sea_orm(belongs_to, from = "note_id", to = "id")]
+    #[sea_orm(belongs_to, from = "note_id", to = "id", on_delete = "Cascade")]
     pub note: HasOne<super::note::Entity>,
 }
 
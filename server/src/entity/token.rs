@@ -1,5 +1,38 @@
 use chrono::{DateTime, Utc};
 use sea_orm::entity::prelude::*;
+use strum::{AsRefStr, EnumString};
+use utoipa::ToSchema;
+
+/// What a personal access token may be used for
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    AsRefStr,
+    EnumString,
+    serde::Serialize,
+    serde::Deserialize,
+    ToSchema,
+)]
+pub enum Scope {
+    #[strum(serialize = "notes:read")]
+    #[serde(rename = "notes:read")]
+    NotesRead,
+    #[strum(serialize = "notes:write")]
+    #[serde(rename = "notes:write")]
+    NotesWrite,
+    #[strum(serialize = "files:read")]
+    #[serde(rename = "files:read")]
+    FilesRead,
+    #[strum(serialize = "files:write")]
+    #[serde(rename = "files:write")]
+    FilesWrite,
+    #[strum(serialize = "ai:generate")]
+    #[serde(rename = "ai:generate")]
+    AiGenerate,
+}
 
 #[sea_orm::model]
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
@@ -9,13 +42,54 @@ pub struct Model {
     pub id: i32,
 
     pub user_id: i32,
-    #[sea_orm(belongs_to, from = "user_id", to = "id")]
+    #[sea_orm(belongs_to, from = "user_id", to = "id", on_delete = "Cascade")]
     pub user: HasOne<super::user::Entity>,
 
     #[sea_orm(indexed, unique)]
     pub token_hash: String,
 
     pub created_at: DateTime<Utc>,
+
+    /// Only personal access tokens may never expire
+    pub expires_at: Option<DateTime<Utc>>,
+
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub last_used_at: DateTime<Utc>,
+
+    /// Hash of the refresh token that can be exchanged for a new token pair
+    #[sea_orm(indexed, unique)]
+    pub refresh_token_hash: Option<String>,
+
+    pub refresh_expires_at: Option<DateTime<Utc>>,
+
+    /// Hash of the refresh token this one replaced. Seeing it again means it was stolen.
+    #[sea_orm(indexed)]
+    pub previous_refresh_token_hash: Option<String>,
+
+    /// Set for personal access tokens, login sessions have no name
+    pub name: Option<String>,
+
+    /// Space separated scopes of a personal access token. Login sessions have full access.
+    pub scopes: Option<String>,
+}
+
+impl Model {
+    pub fn is_personal(&self) -> bool {
+        self.scopes.is_some()
+    }
+
+    pub fn scopes(&self) -> Vec<Scope> {
+        self.scopes
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter_map(|scope| scope.parse().ok())
+            .collect()
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        !self.is_personal() || self.scopes().contains(&scope)
+    }
 }
 
 impl ActiveModelBehavior for ActiveModel {}
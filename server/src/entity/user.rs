@@ -1,4 +1,31 @@
 use sea_orm::entity::prelude::*;
+use utoipa::ToSchema;
+
+/// Ordered from least to most privileged, so roles can be compared with `>=`
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    DeriveActiveEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    ToSchema,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[sea_orm(string_value = "user")]
+    User,
+    #[sea_orm(string_value = "moderator")]
+    Moderator,
+    #[sea_orm(string_value = "admin")]
+    Admin,
+}
 
 #[sea_orm::model]
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
@@ -16,11 +43,53 @@ pub struct Model {
 
     pub password: String,
 
+    /// Shown instead of the username where set
+    pub display_name: Option<String>,
+
+    pub bio: Option<String>,
+
+    pub school: Option<String>,
+
+    pub class: Option<String>,
+
+    /// BCP 47 language tag, like `en` or `pt-BR`
+    pub preferred_language: Option<String>,
+
+    #[sea_orm(default_value = "user")]
+    pub role: Role,
+
     #[sea_orm(column_type = "DateTime")]
     pub created_at: DateTime,
 
-    /// Raw profile picture bytes (PNG/JPEG/etc). `None` if not set.
-    pub profile_picture: Option<Vec<u8>>,
+    #[sea_orm(column_type = "DateTime")]
+    pub email_verified_at: Option<DateTime>,
+
+    /// Base32 TOTP secret, set once the user starts enrolling
+    pub totp_secret: Option<String>,
+
+    /// Set once the user confirmed a code, two-factor login is only enforced after that
+    #[sea_orm(column_type = "DateTime")]
+    pub totp_enabled_at: Option<DateTime>,
+
+    /// Time step of the last accepted code, so a code can't be used twice
+    pub totp_last_step: Option<i64>,
+
+    /// When the account gets permanently deleted, unless the user logs in before then
+    #[sea_orm(column_type = "DateTime")]
+    pub deletion_scheduled_at: Option<DateTime>,
+
+    /// Whether public notes are kept under the deleted user placeholder when the account is deleted
+    #[sea_orm(default_value = false)]
+    pub deletion_keeps_public_notes: bool,
+
+    /// Where the profile picture is kept in storage. `None` if not set.
+    pub profile_picture_key: Option<String>,
+
+    /// MIME type of the profile picture
+    pub profile_picture_content_type: Option<String>,
+
+    /// Size of the profile picture, in bytes
+    pub profile_picture_size: Option<i64>,
 }
 
 impl ActiveModelBehavior for ActiveModel {}
\ No newline at end of file
@@ -1,9 +1,10 @@
 use axum::{
     body::Body,
-    http::StatusCode,
+    http::{HeaderName, HeaderValue, StatusCode, header::RETRY_AFTER},
     response::{IntoResponse, Response},
 };
 use color_eyre::Report;
+use sea_orm::{DbErr, RuntimeErr, sqlx::postgres::PgDatabaseError};
 use serde::Serialize;
 use serde_json::json;
 use tracing::error;
@@ -13,6 +14,11 @@ use utoipa::ToSchema;
 pub struct AxumError {
     pub report: Report,
     pub status_code: StatusCode,
+    /// Machine-readable error code, e.g. `token_expired`
+    pub code: Option<&'static str>,
+    /// Request field the error is about, e.g. `username`
+    pub field: Option<String>,
+    pub headers: Vec<(HeaderName, HeaderValue)>,
 }
 
 impl AxumError {
@@ -20,6 +26,9 @@ impl AxumError {
         Self {
             report,
             status_code: StatusCode::INTERNAL_SERVER_ERROR,
+            code: None,
+            field: None,
+            headers: Vec::new(),
         }
     }
 
@@ -27,9 +36,22 @@ impl AxumError {
         Self {
             report,
             status_code,
+            code: None,
+            field: None,
+            headers: Vec::new(),
         }
     }
 
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+
     pub fn bad_request(report: Report) -> Self {
         Self::with_status(report, StatusCode::BAD_REQUEST)
     }
@@ -50,28 +72,140 @@ impl AxumError {
         Self::with_status(report, StatusCode::CONFLICT)
     }
 
+    pub fn unsupported_media_type(report: Report) -> Self {
+        Self::with_status(report, StatusCode::UNSUPPORTED_MEDIA_TYPE)
+    }
+
     pub fn unprocessable_entity(report: Report) -> Self {
         Self::with_status(report, StatusCode::UNPROCESSABLE_ENTITY)
     }
+
+    pub fn too_many_requests(report: Report, retry_after: u64) -> Self {
+        let mut error =
+            Self::with_status(report, StatusCode::TOO_MANY_REQUESTS).with_code("rate_limited");
+        error
+            .headers
+            .push((RETRY_AFTER, HeaderValue::from(retry_after)));
+        error
+    }
 }
 
 impl<E: Into<Report>> From<E> for AxumError {
     fn from(error: E) -> Self {
-        Self::new(error.into())
+        let report = error.into();
+
+        let violation = report
+            .downcast_ref::<DbErr>()
+            .and_then(pg_database_error)
+            .and_then(ConstraintViolation::from_pg_error);
+
+        match violation {
+            Some(violation) => Self {
+                report: report.wrap_err(violation.message),
+                status_code: violation.status_code,
+                code: Some(violation.code),
+                field: violation.field,
+                headers: Vec::new(),
+            },
+            None => Self::new(report),
+        }
+    }
+}
+
+fn pg_database_error(error: &DbErr) -> Option<&PgDatabaseError> {
+    let (DbErr::Exec(RuntimeErr::SqlxError(error)) | DbErr::Query(RuntimeErr::SqlxError(error))) =
+        error
+    else {
+        return None;
+    };
+
+    match error.as_ref() {
+        sea_orm::sqlx::Error::Database(error) => error.try_downcast_ref::<PgDatabaseError>(),
+        _ => None,
+    }
+}
+
+/// A Postgres integrity constraint violation, translated into a client error
+struct ConstraintViolation {
+    status_code: StatusCode,
+    code: &'static str,
+    field: Option<String>,
+    message: String,
+}
+
+impl ConstraintViolation {
+    fn from_pg_error(error: &PgDatabaseError) -> Option<Self> {
+        // Postgres only names the column for not-null violations, the others carry it in the
+        // detail, e.g. `Key (user_id, note_id)=(1, 2) already exists.`
+        let field = error.column().map(str::to_string).or_else(|| {
+            let columns = error.detail()?.strip_prefix("Key (")?.split_once(")=")?.0;
+            Some(columns.to_string())
+        });
+        let subject = field.as_deref().unwrap_or("value");
+
+        let (status_code, code, message) = match error.code() {
+            "23505" => (
+                StatusCode::CONFLICT,
+                "already_exists",
+                format!("{subject} is already taken"),
+            ),
+            "23503"
+                if error
+                    .detail()
+                    .is_some_and(|d| d.contains("is still referenced")) =>
+            {
+                (
+                    StatusCode::CONFLICT,
+                    "still_referenced",
+                    "Resource is still in use".to_string(),
+                )
+            }
+            "23503" => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_reference",
+                format!("{subject} refers to a resource that doesn't exist"),
+            ),
+            "23502" => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "missing_field",
+                format!("{subject} is required"),
+            ),
+            "23514" => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_value",
+                format!("{subject} is invalid"),
+            ),
+            _ => return None,
+        };
+
+        Some(Self {
+            status_code,
+            code,
+            field,
+            message,
+        })
     }
 }
 
 impl IntoResponse for AxumError {
     fn into_response(self) -> Response {
         error!(error = ?self.report, "An error occurred in an axum handler");
-        let body = json!({
+        let mut body = json!({
             "error": self.report.to_string()
         });
-        Response::builder()
+        if let Some(code) = self.code {
+            body["code"] = code.into();
+        }
+        if let Some(field) = self.field {
+            body["field"] = field.into();
+        }
+        let mut response = Response::builder()
             .status(self.status_code)
             .header("Content-Type", "application/json")
             .body(Body::from(body.to_string()))
-            .unwrap_or_else(|e| format!("{e:?}").into_response())
+            .unwrap_or_else(|e| format!("{e:?}").into_response());
+        response.headers_mut().extend(self.headers);
+        response
     }
 }
 
@@ -82,3 +216,11 @@ pub type AxumResult<T, E = AxumError> = std::result::Result<T, E>;
 pub struct NotFoundError {
     error: String,
 }
+
+#[derive(Serialize, ToSchema)]
+#[schema(example = json!({"error": "username is already taken", "code": "already_exists", "field": "username"}))]
+pub struct ConstraintError {
+    error: String,
+    code: String,
+    field: Option<String>,
+}
@@ -1,10 +1,13 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
 
 use async_openai::config::OpenAIConfig;
 use axum::{Extension, Json, Router, response::IntoResponse, routing::get};
 use color_eyre::Result;
 use http::StatusCode;
-use sea_orm::Database;
+use redis::aio::ConnectionManager;
+use sea_orm::{
+    ColumnTrait, Database, DatabaseConnection, EntityTrait, QueryFilter, sea_query::Expr,
+};
 use tokio::net::TcpListener;
 use tracing::{instrument, level_filters::LevelFilter};
 use tracing_error::ErrorLayer;
@@ -13,7 +16,18 @@ use utoipa_rapidoc::RapiDoc;
 use utoipa_redoc::{Redoc, Servable};
 use utoipa_scalar::{Scalar, Servable as _};
 
-use crate::{settings::Settings, state::AppState};
+use crate::{
+    entity::user::{self, Role},
+    mailer::{LogMailer, Mailer, SmtpMailer},
+    migrations::run_migrations,
+    settings::{MailTransport, Settings, StorageBackend},
+    state::AppState,
+    storage::{
+        LocalStorage, S3Storage, Storage,
+        migrate::{backfill_content_types, migrate_database_blobs},
+    },
+    util::{export::init_export_indexes, search::init_search_columns},
+};
 
 pub fn init_tracing(filter: LevelFilter) -> Result<()> {
     tracing_subscriber::Registry::default()
@@ -59,15 +73,47 @@ pub async fn init_listener(settings: &Settings) -> Result<TcpListener> {
     Ok(TcpListener::bind(addr.as_slice()).await?)
 }
 
-pub async fn init_database(settings: &Settings) -> Result<sea_orm::DatabaseConnection> {
+pub async fn init_database(
+    settings: &Settings,
+    storage: &dyn Storage,
+) -> Result<sea_orm::DatabaseConnection> {
     let db = Database::connect(settings.db.connection_string.clone()).await?;
+
+    // Have to go first, schema sync can't add new non-null columns to existing rows
+    run_migrations(&db, settings).await?;
+    migrate_database_blobs(&db, storage).await?;
+    backfill_content_types(&db, storage).await?;
+
     db.get_schema_registry("server::entity::*")
         .sync(&db)
         .await?;
 
+    init_search_columns(&db).await?;
+    init_export_indexes(&db).await?;
+
     Ok(db)
 }
 
+pub async fn init_admins(settings: &Settings, db: &DatabaseConnection) -> Result<()> {
+    if settings.auth.admin_usernames.is_empty() {
+        return Ok(());
+    }
+
+    user::Entity::update_many()
+        .col_expr(user::Column::Role, Expr::value(Role::Admin))
+        .filter(user::Column::Username.is_in(settings.auth.admin_usernames.clone()))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn init_redis(settings: &Settings) -> Result<ConnectionManager> {
+    let client = redis::Client::open(settings.redis.connection_string.as_str())?;
+
+    Ok(ConnectionManager::new(client).await?)
+}
+
 pub fn init_ai(settings: &Settings) -> async_openai::Client<OpenAIConfig> {
     async_openai::Client::with_config(
         OpenAIConfig::new()
@@ -75,3 +121,31 @@ pub fn init_ai(settings: &Settings) -> async_openai::Client<OpenAIConfig> {
             .with_api_base(&settings.ai.base_url),
     )
 }
+
+pub fn init_storage(settings: &Settings) -> Result<Arc<dyn Storage>> {
+    Ok(match &settings.storage {
+        StorageBackend::Local { directory } => Arc::new(LocalStorage::new(directory.clone())),
+        StorageBackend::S3 {
+            bucket,
+            region,
+            endpoint,
+            access_key_id,
+            secret_access_key,
+        } => Arc::new(S3Storage::new(
+            bucket,
+            region,
+            endpoint.as_deref(),
+            access_key_id.as_deref(),
+            secret_access_key.as_deref(),
+        )?),
+    })
+}
+
+pub fn init_mailer(settings: &Settings) -> Result<Arc<dyn Mailer>> {
+    let mail = &settings.mail;
+
+    Ok(match &mail.transport {
+        MailTransport::Smtp { url } => Arc::new(SmtpMailer::new(url, &mail.from)?),
+        MailTransport::Log { directory } => Arc::new(LogMailer::new(&mail.from, directory.clone())),
+    })
+}
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use color_eyre::Result;
+use sea_orm::{
+    ColumnTrait, Condition, EntityTrait, QueryFilter, QuerySelect, TransactionTrait,
+    sea_query::Expr,
+};
+use tracing::{error, info};
+
+use crate::{
+    entity::{
+        export::{self, ExportStatus},
+        note, user, username_reservation,
+    },
+    state::AppState,
+    util::cleanup::delete_account,
+};
+
+/// How often periodic cleanup runs
+const INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Exports still pending after this long were interrupted
+const STALE_EXPORT_AGE: TimeDelta = TimeDelta::hours(1);
+
+/// Starts the periodic background jobs
+pub fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(error) = purge_deleted_accounts(&state).await {
+                error!(error = ?error, "Failed to purge deleted accounts");
+            }
+
+            if let Err(error) = purge_expired_exports(&state).await {
+                error!(error = ?error, "Failed to purge expired exports");
+            }
+
+            if let Err(error) = purge_expired_username_reservations(&state).await {
+                error!(error = ?error, "Failed to purge expired username reservations");
+            }
+
+            if let Err(error) = purge_trashed_notes(&state).await {
+                error!(error = ?error, "Failed to purge trashed notes");
+            }
+        }
+    });
+}
+
+/// Permanently deletes accounts whose deletion grace period is over
+async fn purge_deleted_accounts(state: &AppState) -> Result<()> {
+    let users = user::Entity::find()
+        .filter(user::Column::DeletionScheduledAt.lte(Utc::now().naive_utc()))
+        .all(&state.db)
+        .await?;
+
+    // One account failing to delete shouldn't keep the others around
+    for user in users {
+        if let Err(error) = purge_account(state, &user).await {
+            error!(error = ?error, user_id = user.id, "Failed to delete account");
+        }
+    }
+
+    Ok(())
+}
+
+async fn purge_account(state: &AppState, user: &user::Model) -> Result<()> {
+    let txn = state.db.begin().await?;
+    let storage_keys = delete_account(&txn, user.id, user.deletion_keeps_public_notes).await?;
+    txn.commit().await?;
+
+    state.storage.delete_all(&storage_keys).await;
+
+    info!(user_id = user.id, "Deleted account");
+
+    Ok(())
+}
+
+/// Drops archives whose download link expired, and gives up on exports a restart interrupted
+async fn purge_expired_exports(state: &AppState) -> Result<()> {
+    let now = Utc::now();
+
+    let expired: Vec<(i32, Option<String>)> = export::Entity::find()
+        .select_only()
+        .columns([export::Column::Id, export::Column::StorageKey])
+        .filter(export::Column::ExpiresAt.lte(now))
+        .filter(
+            Condition::any()
+                .add(export::Column::DownloadTokenHash.is_not_null())
+                .add(export::Column::StorageKey.is_not_null()),
+        )
+        .into_tuple()
+        .all(&state.db)
+        .await?;
+
+    if !expired.is_empty() {
+        let (ids, storage_keys): (Vec<i32>, Vec<Option<String>>) = expired.into_iter().unzip();
+
+        export::Entity::update_many()
+            .col_expr(
+                export::Column::DownloadTokenHash,
+                Expr::value(Option::<String>::None),
+            )
+            .col_expr(
+                export::Column::StorageKey,
+                Expr::value(Option::<String>::None),
+            )
+            .col_expr(export::Column::Size, Expr::value(Option::<i64>::None))
+            .filter(export::Column::Id.is_in(ids))
+            .exec(&state.db)
+            .await?;
+
+        let storage_keys: Vec<String> = storage_keys.into_iter().flatten().collect();
+        state.storage.delete_all(&storage_keys).await;
+    }
+
+    export::Entity::update_many()
+        .col_expr(export::Column::Status, Expr::value(ExportStatus::Failed))
+        .col_expr(export::Column::FinishedAt, Expr::value(now))
+        .filter(export::Column::Status.eq(ExportStatus::Pending))
+        .filter(export::Column::CreatedAt.lte(now - STALE_EXPORT_AGE))
+        .exec(&state.db)
+        .await?;
+
+    Ok(())
+}
+
+/// Frees usernames whose reservation ran out
+async fn purge_expired_username_reservations(state: &AppState) -> Result<()> {
+    username_reservation::Entity::delete_many()
+        .filter(username_reservation::Column::ExpiresAt.lte(Utc::now()))
+        .exec(&state.db)
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes notes that have been in the trash longer than the retention period
+async fn purge_trashed_notes(state: &AppState) -> Result<()> {
+    let cutoff = Utc::now() - TimeDelta::seconds(state.settings.notes.trash_retention);
+
+    // Their quizzes, votes, tags and revisions go with them through the foreign keys
+    let result = note::Entity::delete_many()
+        .filter(note::Column::DeletedAt.lte(cutoff))
+        .exec(&state.db)
+        .await?;
+
+    if result.rows_affected > 0 {
+        info!(count = result.rows_affected, "Purged trashed notes");
+    }
+
+    Ok(())
+}
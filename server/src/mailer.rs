@@ -0,0 +1,20 @@
+pub mod log;
+pub mod smtp;
+
+use async_trait::async_trait;
+use color_eyre::Result;
+
+pub use log::LogMailer;
+pub use smtp::SmtpMailer;
+
+#[derive(Clone, Debug)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<()>;
+}
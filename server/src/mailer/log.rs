@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use color_eyre::Result;
+use tracing::info;
+
+use super::{Email, Mailer};
+
+/// Logs outgoing emails instead of delivering them, optionally also writing each one to a file
+pub struct LogMailer {
+    from: String,
+    directory: Option<PathBuf>,
+}
+
+impl LogMailer {
+    pub fn new(from: &str, directory: Option<PathBuf>) -> Self {
+        Self {
+            from: from.to_string(),
+            directory,
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> Result<()> {
+        info!(to = %email.to, subject = %email.subject, body = %email.body, "Sending email");
+
+        if let Some(directory) = &self.directory {
+            tokio::fs::create_dir_all(directory).await?;
+
+            let path = directory.join(format!(
+                "{}-{}.eml",
+                Utc::now().format("%Y%m%dT%H%M%S%.6f"),
+                sanitize_filename::sanitize(&email.to)
+            ));
+            let contents = format!(
+                "From: {}\nTo: {}\nSubject: {}\n\n{}\n",
+                self.from, email.to, email.subject, email.body
+            );
+
+            tokio::fs::write(path, contents).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,13 +1,18 @@
 mod entity;
 mod errors;
 mod init;
+mod jobs;
+mod mailer;
 mod middlewares;
+mod migrations;
+mod policy;
 mod routes;
 mod settings;
 mod state;
+mod storage;
 mod util;
 
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use color_eyre::{Result, eyre::Context};
 use rustls::crypto::{CryptoProvider, aws_lc_rs};
@@ -15,9 +20,13 @@ use tracing::{info, level_filters::LevelFilter};
 use utoipa::OpenApi;
 
 use crate::{
-    init::{init_ai, init_axum, init_database, init_listener, init_tracing},
+    init::{
+        init_admins, init_ai, init_axum, init_database, init_listener, init_mailer, init_redis,
+        init_storage, init_tracing,
+    },
     settings::Settings,
     state::AppState,
+    util::cleanup::ensure_deleted_user,
 };
 
 #[derive(OpenApi)]
@@ -43,16 +52,31 @@ async fn main() -> Result<()> {
 
     let settings = Arc::new(Settings::try_load()?);
 
-    let db = init_database(&settings).await?;
+    let storage = init_storage(&settings)?;
+
+    let db = init_database(&settings, storage.as_ref()).await?;
+
+    init_admins(&settings, &db).await?;
+
+    ensure_deleted_user(&db).await?;
+
+    let redis = init_redis(&settings).await?;
 
     let ai = init_ai(&settings);
 
+    let mailer = init_mailer(&settings)?;
+
     let app_state = AppState {
         settings: settings.clone(),
         db,
+        redis,
         ai,
+        mailer,
+        storage,
     };
 
+    jobs::spawn(app_state.clone());
+
     let app = init_axum(app_state).await?;
     let listener = init_listener(&settings).await?;
 
@@ -64,9 +88,12 @@ async fn main() -> Result<()> {
         settings.general.public_url
     );
 
-    axum::serve(listener, app.into_make_service())
-        .await
-        .wrap_err("failed to run server")?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .wrap_err("failed to run server")?;
 
     Ok(())
 }
@@ -1,2 +1,8 @@
 pub mod auth;
+pub mod rate_limit;
+pub mod role;
+pub mod scope;
 pub use auth::*;
+pub use rate_limit::*;
+pub use role::*;
+pub use scope::*;
@@ -1,6 +1,8 @@
 use axum::{Extension, extract::Request, middleware::Next, response::Response};
+use chrono::{TimeDelta, Utc};
 use color_eyre::eyre::eyre;
 use http::header::AUTHORIZATION;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set};
 use serde::Serialize;
 use utoipa::ToSchema;
 
@@ -11,6 +13,9 @@ use crate::{
     util::tokens::hash_token,
 };
 
+/// How stale `last_used_at` may get before it is written back, to avoid a write on every request
+const LAST_USED_RESOLUTION: TimeDelta = TimeDelta::minutes(1);
+
 pub async fn with_auth(
     Extension(state): Extension<AppState>,
     mut request: Request,
@@ -38,6 +43,25 @@ pub async fn with_auth(
         return Err(AxumError::unauthorized(eyre!("Unauthorized")));
     };
 
+    let now = Utc::now();
+    let idle_timeout = TimeDelta::seconds(state.settings.auth.idle_timeout);
+
+    // Personal access tokens are meant for scripts that may run rarely, so they don't idle out
+    let expired = token.expires_at.is_some_and(|expires_at| expires_at <= now)
+        || (!token.is_personal() && now - token.last_used_at > idle_timeout);
+
+    if expired {
+        return Err(AxumError::unauthorized(eyre!("Token expired")).with_code("token_expired"));
+    }
+
+    let token = if now - token.last_used_at > LAST_USED_RESOLUTION {
+        let mut token: token::ActiveModel = token.into();
+        token.last_used_at = Set(now);
+        token.update(&state.db).await?
+    } else {
+        token
+    };
+
     request.extensions_mut().insert(user);
     request.extensions_mut().insert(token);
 
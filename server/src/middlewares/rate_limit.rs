@@ -0,0 +1,251 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    Extension,
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use color_eyre::eyre::eyre;
+use redis::{AsyncCommands, RedisResult};
+use tracing::warn;
+
+use crate::{
+    entity::user,
+    errors::{AxumError, AxumResult},
+    settings::{RateLimit, RateLimitRule},
+    state::AppState,
+};
+
+/// Which `RateLimit` rule a route group is counted against
+#[derive(Clone, Copy, Debug)]
+pub enum RateLimitGroup {
+    Api,
+    Auth,
+    Ai,
+}
+
+impl RateLimitGroup {
+    fn name(self) -> &'static str {
+        match self {
+            RateLimitGroup::Api => "api",
+            RateLimitGroup::Auth => "auth",
+            RateLimitGroup::Ai => "ai",
+        }
+    }
+
+    fn rule(self, settings: &RateLimit) -> &RateLimitRule {
+        match self {
+            RateLimitGroup::Api => &settings.api,
+            RateLimitGroup::Auth => &settings.auth,
+            RateLimitGroup::Ai => &settings.ai,
+        }
+    }
+}
+
+/// The address a request came from, added to the request by `rate_limit`
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+/// Counts the request against the per-IP and per-user buckets of `group`.
+///
+/// Must run after `with_auth` for the per-user bucket to apply.
+pub async fn rate_limit(
+    State(group): State<RateLimitGroup>,
+    Extension(state): Extension<AppState>,
+    mut request: Request,
+    next: Next,
+) -> AxumResult<Response> {
+    let settings = &state.settings.rate_limit;
+
+    let client_ip = client_ip(&request, settings.trusted_proxies);
+    request.extensions_mut().insert(ClientIp(client_ip));
+
+    if !settings.enabled {
+        return Ok(next.run(request).await);
+    }
+
+    let rule = group.rule(settings);
+
+    if let Some(limit) = rule.per_ip
+        && let Some(ip) = client_ip
+    {
+        let key = format!("ratelimit:{}:ip:{ip}", group.name());
+        hit(&state, &key, limit, rule.window).await?;
+    }
+
+    if let Some(limit) = rule.per_user
+        && let Some(user) = request.extensions().get::<user::Model>()
+    {
+        let key = format!("ratelimit:{}:user:{}", group.name(), user.id);
+        hit(&state, &key, limit, rule.window).await?;
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Each trusted proxy appends the address it received the request from, so the entry
+/// `trusted_proxies` from the right is the last one not under the client's control
+fn client_ip(request: &Request, trusted_proxies: usize) -> Option<IpAddr> {
+    if trusted_proxies > 0 {
+        let forwarded: Vec<&str> = request
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+
+        let forwarded = forwarded
+            .iter()
+            .rev()
+            .nth(trusted_proxies - 1)
+            .and_then(|ip| ip.trim().parse().ok());
+
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+/// Counts one request in a fixed window and rejects it once `limit` is exceeded.
+///
+/// Redis being unavailable lets the request through rather than taking the API down with it.
+async fn hit(state: &AppState, key: &str, limit: u64, window: u64) -> AxumResult<()> {
+    let mut redis = state.redis.clone();
+
+    let result: RedisResult<(u64, i64)> = redis::pipe()
+        .atomic()
+        .cmd("SET")
+        .arg(key)
+        .arg(0)
+        .arg("NX")
+        .arg("EX")
+        .arg(window)
+        .ignore()
+        .incr(key, 1)
+        .ttl(key)
+        .query_async(&mut redis)
+        .await;
+
+    match result {
+        Ok((count, ttl)) if count > limit => Err(AxumError::too_many_requests(
+            eyre!("Too many requests"),
+            ttl.max(1) as u64,
+        )),
+        Ok(_) => Ok(()),
+        Err(error) => {
+            warn!(error = ?error, key, "Rate limiting is unavailable");
+            Ok(())
+        }
+    }
+}
+
+/// Failures are counted per username and IP, so someone guessing at an account can't lock its
+/// owner out from everywhere else
+fn lockout_key(username: &str, ip: Option<IpAddr>) -> String {
+    let ip = ip.map_or_else(|| "unknown".to_owned(), |ip| ip.to_string());
+    format!("lockout:login:{}:{ip}", username.to_lowercase())
+}
+
+/// Failures for a username across every IP, so rotating addresses doesn't buy unlimited guesses
+fn username_lockout_key(username: &str) -> String {
+    format!("lockout:login-username:{}", username.to_lowercase())
+}
+
+/// Rejects logins for a username that has failed too many times recently from `ip`, or from
+/// anywhere
+pub async fn check_login_lockout(
+    state: &AppState,
+    username: &str,
+    ip: Option<IpAddr>,
+) -> AxumResult<()> {
+    let settings = &state.settings.rate_limit;
+
+    if !settings.enabled {
+        return Ok(());
+    }
+
+    let mut redis = state.redis.clone();
+    let key = lockout_key(username, ip);
+    let username_key = username_lockout_key(username);
+
+    let result: RedisResult<(Option<u64>, i64, Option<u64>, i64)> = redis::pipe()
+        .get(&key)
+        .ttl(&key)
+        .get(&username_key)
+        .ttl(&username_key)
+        .query_async(&mut redis)
+        .await;
+
+    let lockout = &settings.login_lockout;
+
+    match result {
+        Ok((Some(failures), ttl, _, _)) if failures >= lockout.max_failures => {
+            Err(AxumError::too_many_requests(
+                eyre!("Too many failed login attempts, try again later"),
+                ttl.max(1) as u64,
+            ))
+        }
+        Ok((_, _, Some(failures), ttl)) if failures >= lockout.max_failures_per_username => {
+            Err(AxumError::too_many_requests(
+                eyre!("Too many failed login attempts, try again later"),
+                ttl.max(1) as u64,
+            ))
+        }
+        Ok(_) => Ok(()),
+        Err(error) => {
+            warn!(error = ?error, "Login lockout is unavailable");
+            Ok(())
+        }
+    }
+}
+
+pub async fn record_login_failure(state: &AppState, username: &str, ip: Option<IpAddr>) {
+    let settings = &state.settings.rate_limit;
+
+    if !settings.enabled {
+        return;
+    }
+
+    let mut redis = state.redis.clone();
+    let key = lockout_key(username, ip);
+    let username_key = username_lockout_key(username);
+    let duration = settings.login_lockout.duration as i64;
+
+    let result: RedisResult<()> = redis::pipe()
+        .atomic()
+        .incr(&key, 1)
+        .ignore()
+        .expire(&key, duration)
+        .ignore()
+        .incr(&username_key, 1)
+        .ignore()
+        .expire(&username_key, duration)
+        .ignore()
+        .query_async(&mut redis)
+        .await;
+
+    if let Err(error) = result {
+        warn!(error = ?error, "Failed to record login failure");
+    }
+}
+
+/// Only the count for `ip` is cleared. The username-wide count is left to expire, so the owner
+/// logging in doesn't reset guesses made from elsewhere.
+pub async fn clear_login_failures(state: &AppState, username: &str, ip: Option<IpAddr>) {
+    if !state.settings.rate_limit.enabled {
+        return;
+    }
+
+    let mut redis = state.redis.clone();
+
+    if let Err(error) = redis.del::<_, ()>(lockout_key(username, ip)).await {
+        warn!(error = ?error, "Failed to clear login failures");
+    }
+}
@@ -0,0 +1,28 @@
+use axum::{
+    Extension,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use color_eyre::eyre::eyre;
+
+use crate::{
+    entity::user::{self, Role},
+    errors::{AxumError, AxumResult},
+};
+
+/// Rejects users below `role`. Must run after `with_auth`.
+pub async fn require_role(
+    State(role): State<Role>,
+    Extension(user): Extension<user::Model>,
+    request: Request,
+    next: Next,
+) -> AxumResult<Response> {
+    if user.role < role {
+        return Err(AxumError::forbidden(eyre!(
+            "You do not have permission to do this"
+        )));
+    }
+
+    Ok(next.run(request).await)
+}
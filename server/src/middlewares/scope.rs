@@ -0,0 +1,82 @@
+use axum::{
+    Extension,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use color_eyre::eyre::eyre;
+
+use crate::{
+    entity::token::{self, Scope},
+    errors::{AxumError, AxumResult},
+};
+
+/// Scopes a personal access token needs for a group of routes. `None` keeps them to login sessions.
+#[derive(Clone, Copy)]
+pub struct ScopeRule {
+    /// Needed for read-only methods like `GET`
+    pub read: Option<Scope>,
+    /// Needed for every other method
+    pub write: Option<Scope>,
+}
+
+impl ScopeRule {
+    pub const SESSION_ONLY: Self = Self {
+        read: None,
+        write: None,
+    };
+
+    pub const fn new(read: Scope, write: Scope) -> Self {
+        Self {
+            read: Some(read),
+            write: Some(write),
+        }
+    }
+
+    /// Needs `scope` whatever the method
+    pub const fn all(scope: Scope) -> Self {
+        Self::new(scope, scope)
+    }
+
+    pub const fn read_only(read: Scope) -> Self {
+        Self {
+            read: Some(read),
+            write: None,
+        }
+    }
+}
+
+/// Rejects personal access tokens missing the scope for the request. Must run after `with_auth`.
+pub async fn require_scope(
+    State(rule): State<ScopeRule>,
+    Extension(token): Extension<token::Model>,
+    request: Request,
+    next: Next,
+) -> AxumResult<Response> {
+    if !token.is_personal() {
+        return Ok(next.run(request).await);
+    }
+
+    let scope = if request.method().is_safe() {
+        rule.read
+    } else {
+        rule.write
+    };
+
+    let Some(scope) = scope else {
+        return Err(
+            AxumError::forbidden(eyre!("Personal access tokens can't be used for this"))
+                .with_code("insufficient_scope"),
+        );
+    };
+
+    if !token.has_scope(scope) {
+        return Err(AxumError::forbidden(eyre!(
+            "This token is missing the `{}` scope",
+            scope.as_ref()
+        ))
+        .with_code("insufficient_scope"));
+    }
+
+    Ok(next.run(request).await)
+}
@@ -0,0 +1,238 @@
+use color_eyre::Result;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement, TransactionTrait};
+use tracing::info;
+
+use crate::settings::Settings;
+
+/// One-shot fixes for databases created by older versions. Schema sync only adds tables and
+/// columns, so anything it can't do runs here first. Each step checks the schema before doing
+/// anything, so it only ever runs once.
+pub async fn run_migrations(db: &DatabaseConnection, settings: &Settings) -> Result<()> {
+    backfill_token_expiry(db, settings).await?;
+    allow_tokens_without_expiry(db).await?;
+    backfill_email_verification(db).await?;
+    cascade_foreign_keys(db).await?;
+    hash_export_download_tokens(db).await?;
+    repair_note_tags(db).await?;
+
+    Ok(())
+}
+
+/// Login sessions from before tokens expired get the expiry they would have had, instead of
+/// living forever
+async fn backfill_token_expiry(db: &DatabaseConnection, settings: &Settings) -> Result<()> {
+    if !column_exists(db, "tokens", "id").await?
+        || column_exists(db, "tokens", "expires_at").await?
+    {
+        return Ok(());
+    }
+
+    let txn = db.begin().await?;
+    txn.execute_unprepared("ALTER TABLE tokens ADD COLUMN expires_at timestamptz")
+        .await?;
+    let backfilled = txn
+        .execute_raw(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "UPDATE tokens SET expires_at = created_at + make_interval(secs => $1)",
+            [settings.auth.access_token_lifetime.into()],
+        ))
+        .await?;
+    txn.commit().await?;
+
+    info!(
+        count = backfilled.rows_affected(),
+        "Backfilled token expiry"
+    );
+
+    Ok(())
+}
+
+/// Personal access tokens may never expire, but databases from before them require an expiry
+async fn allow_tokens_without_expiry(db: &DatabaseConnection) -> Result<()> {
+    if column_nullable(db, "tokens", "expires_at").await? != Some(false) {
+        return Ok(());
+    }
+
+    db.execute_unprepared("ALTER TABLE tokens ALTER COLUMN expires_at DROP NOT NULL")
+        .await?;
+
+    info!("Allowed tokens without expiry");
+
+    Ok(())
+}
+
+/// Accounts from before email verification count as verified, otherwise they'd all lose publishing
+/// and AI generation at once
+async fn backfill_email_verification(db: &DatabaseConnection) -> Result<()> {
+    if !column_exists(db, "users", "id").await?
+        || column_exists(db, "users", "email_verified_at").await?
+    {
+        return Ok(());
+    }
+
+    let txn = db.begin().await?;
+    txn.execute_unprepared("ALTER TABLE users ADD COLUMN email_verified_at timestamp")
+        .await?;
+    let backfilled = txn
+        .execute_unprepared("UPDATE users SET email_verified_at = created_at")
+        .await?;
+    txn.commit().await?;
+
+    info!(
+        count = backfilled.rows_affected(),
+        "Marked existing accounts as verified"
+    );
+
+    Ok(())
+}
+
+/// Foreign keys as (table, column, referenced table). Schema sync creates them with
+/// `ON DELETE CASCADE` from the entity relations, but never changes ones that already exist.
+const CASCADING_FOREIGN_KEYS: &[(&str, &str, &str)] = &[
+    ("blocks", "user_id", "users"),
+    ("blocks", "target_id", "users"),
+    ("email_verifications", "user_id", "users"),
+    ("exports", "user_id", "users"),
+    ("files", "user_id", "users"),
+    ("follows", "follower_id", "users"),
+    ("follows", "followee_id", "users"),
+    ("note_files", "note_id", "notes"),
+    ("note_files", "file_id", "files"),
+    ("note_revisions", "note_id", "notes"),
+    ("note_revisions", "author_id", "users"),
+    ("note_tags", "note_id", "notes"),
+    ("note_tags", "tag_id", "tags"),
+    ("notes", "user_id", "users"),
+    ("oidc_identities", "user_id", "users"),
+    ("password_resets", "user_id", "users"),
+    ("questions", "quiz_id", "quizes"),
+    ("quizes", "note_id", "notes"),
+    ("recovery_codes", "user_id", "users"),
+    ("saves", "user_id", "users"),
+    ("saves", "note_id", "notes"),
+    ("tokens", "user_id", "users"),
+    ("upvotes", "user_id", "users"),
+    ("upvotes", "note_id", "notes"),
+    ("username_reservations", "user_id", "users"),
+];
+
+/// Databases created before the relations cascaded have foreign keys that block deleting a user
+/// or note, so they get recreated to cascade like new ones
+async fn cascade_foreign_keys(db: &DatabaseConnection) -> Result<()> {
+    let txn = db.begin().await?;
+    let mut count = 0;
+
+    for (table, column, referenced) in CASCADING_FOREIGN_KEYS {
+        let constraints = txn
+            .query_all_raw(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                "SELECT c.conname FROM pg_constraint c
+                    JOIN pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = c.conkey[1]
+                    WHERE c.contype = 'f' AND c.confdeltype <> 'c'
+                        AND c.conrelid = to_regclass($1) AND a.attname = $2",
+                [(*table).into(), (*column).into()],
+            ))
+            .await?;
+
+        for constraint in constraints {
+            let name: String = constraint.try_get("", "conname")?;
+
+            txn.execute_unprepared(&format!(
+                r#"ALTER TABLE "{table}" DROP CONSTRAINT "{name}",
+                    ADD CONSTRAINT "{name}" FOREIGN KEY ("{column}") REFERENCES "{referenced}" (id)
+                    ON DELETE CASCADE"#
+            ))
+            .await?;
+            count += 1;
+        }
+    }
+
+    txn.commit().await?;
+
+    if count > 0 {
+        info!(count, "Made foreign keys cascade");
+    }
+
+    Ok(())
+}
+
+/// Export download tokens used to be stored in plain text. Hashing them in place keeps the links
+/// that were already sent working.
+async fn hash_export_download_tokens(db: &DatabaseConnection) -> Result<()> {
+    if !column_exists(db, "exports", "download_token").await? {
+        return Ok(());
+    }
+
+    let txn = db.begin().await?;
+    txn.execute_unprepared(
+        "ALTER TABLE exports RENAME COLUMN download_token TO download_token_hash",
+    )
+    .await?;
+    let hashed = txn
+        .execute_unprepared(
+            "UPDATE exports
+                SET download_token_hash = encode(sha256(convert_to(download_token_hash, 'UTF8')), 'hex')
+                WHERE download_token_hash IS NOT NULL",
+        )
+        .await?;
+    txn.commit().await?;
+
+    info!(
+        count = hashed.rows_affected(),
+        "Hashed export download tokens"
+    );
+
+    Ok(())
+}
+
+/// note_files used to be mapped onto note_tags, so older databases have a file column and the
+/// wrong primary key there. Nothing ever linked files, so the column holds no data.
+async fn repair_note_tags(db: &DatabaseConnection) -> Result<()> {
+    if !column_exists(db, "note_tags", "file_id").await? {
+        return Ok(());
+    }
+
+    // Dropping the column takes any primary key that included it along
+    let txn = db.begin().await?;
+    txn.execute_unprepared(
+        "ALTER TABLE note_tags DROP COLUMN file_id;
+        DO $$ BEGIN
+            IF NOT EXISTS (
+                SELECT 1 FROM pg_constraint WHERE conrelid = 'note_tags'::regclass AND contype = 'p'
+            ) THEN
+                ALTER TABLE note_tags ADD PRIMARY KEY (note_id, tag_id);
+            END IF;
+        END $$",
+    )
+    .await?;
+    txn.commit().await?;
+
+    info!("Repaired note_tags");
+
+    Ok(())
+}
+
+pub async fn column_exists(db: &impl ConnectionTrait, table: &str, column: &str) -> Result<bool> {
+    Ok(column_nullable(db, table, column).await?.is_some())
+}
+
+/// Whether the column allows nulls, `None` if it doesn't exist
+pub async fn column_nullable(
+    db: &impl ConnectionTrait,
+    table: &str,
+    column: &str,
+) -> Result<Option<bool>> {
+    let row = db
+        .query_one_raw(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT is_nullable FROM information_schema.columns
+                WHERE table_schema = current_schema() AND table_name = $1 AND column_name = $2",
+            [table.into(), column.into()],
+        ))
+        .await?;
+
+    Ok(match row {
+        Some(row) => Some(row.try_get::<String>("", "is_nullable")? == "YES"),
+        None => None,
+    })
+}
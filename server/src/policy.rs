@@ -0,0 +1,68 @@
+use color_eyre::eyre::eyre;
+
+use crate::{
+    entity::{
+        file, note,
+        user::{self, Role},
+    },
+    errors::{AxumError, AxumResult},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Read,
+    Edit,
+    Moderate,
+}
+
+/// Decides what a user may do with a resource
+pub trait Policy {
+    fn allows(&self, user: &user::Model, action: Action) -> bool;
+}
+
+impl Policy for note::Model {
+    fn allows(&self, user: &user::Model, action: Action) -> bool {
+        let is_owner = self.user_id == user.id;
+
+        match action {
+            // Moderators see hidden notes to review them, private ones stay private
+            Action::Read => {
+                is_owner
+                    || (self.public && self.hidden_at.is_none())
+                    || (self.public && user.role >= Role::Moderator)
+            }
+            Action::Edit => is_owner,
+            Action::Moderate => user.role >= Role::Moderator,
+        }
+    }
+}
+
+impl Policy for file::Model {
+    fn allows(&self, user: &user::Model, action: Action) -> bool {
+        match action {
+            Action::Read | Action::Edit => self.user_id == user.id,
+            Action::Moderate => user.role >= Role::Moderator,
+        }
+    }
+}
+
+impl Policy for user::Model {
+    fn allows(&self, user: &user::Model, action: Action) -> bool {
+        match action {
+            Action::Read => true,
+            Action::Edit => self.id == user.id || user.role >= Role::Admin,
+            Action::Moderate => user.role >= Role::Admin,
+        }
+    }
+}
+
+/// Rejects with 403 unless `user` may perform `action` on `resource`
+pub fn authorize(user: &user::Model, resource: &impl Policy, action: Action) -> AxumResult<()> {
+    if resource.allows(user, action) {
+        Ok(())
+    } else {
+        Err(AxumError::forbidden(eyre!(
+            "You do not have permission to do this"
+        )))
+    }
+}
@@ -0,0 +1,20 @@
+mod users;
+
+use axum::middleware;
+use utoipa_axum::router::OpenApiRouter;
+
+use crate::{
+    entity::user::Role,
+    middlewares::{ScopeRule, require_role, require_scope},
+    state::AppState,
+};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .nest("/users", users::routes())
+        .layer(middleware::from_fn_with_state(Role::Admin, require_role))
+        .layer(middleware::from_fn_with_state(
+            ScopeRule::SESSION_ONLY,
+            require_scope,
+        ))
+}
@@ -0,0 +1,183 @@
+use axum::{Extension, Json, extract::Path};
+use chrono::NaiveDateTime;
+use color_eyre::eyre::eyre;
+use http::StatusCode;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    entity::{
+        token,
+        user::{self, Role},
+    },
+    errors::{AxumError, AxumResult, NotFoundError},
+    middlewares::UnauthorizedError,
+    state::AppState,
+};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_users))
+        .routes(routes!(get_user, edit_user))
+        .routes(routes!(revoke_user_sessions))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AdminUserResponse {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub role: Role,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<user::Model> for AdminUserResponse {
+    fn from(user: user::Model) -> Self {
+        AdminUserResponse {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            email_verified: user.email_verified_at.is_some(),
+            role: user.role,
+            created_at: user.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ManyAdminUsersResponse {
+    pub users: Vec<AdminUserResponse>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct EditUser {
+    pub role: Option<Role>,
+}
+
+/// List all users (admins only)
+#[utoipa::path(
+    method(get),
+    path = "/",
+    responses(
+        (status = OK, description = "Success", body = ManyAdminUsersResponse),
+        (status = FORBIDDEN, description = "Not an admin"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Admin"
+)]
+async fn get_users(
+    Extension(state): Extension<AppState>,
+) -> AxumResult<Json<ManyAdminUsersResponse>> {
+    let users = user::Entity::find()
+        .order_by_asc(user::Column::Id)
+        .all(&state.db)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+
+    Ok(Json(ManyAdminUsersResponse { users }))
+}
+
+/// Get user details (admins only)
+#[utoipa::path(
+    method(get),
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "User ID")
+    ),
+    responses(
+        (status = OK, description = "Success", body = AdminUserResponse),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = FORBIDDEN, description = "Not an admin"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Admin"
+)]
+async fn get_user(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+) -> AxumResult<Json<AdminUserResponse>> {
+    let user = user::Entity::find_by_id(id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("User not found")))?;
+
+    Ok(Json(user.into()))
+}
+
+/// Edit user (admins only)
+#[utoipa::path(
+    method(patch),
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "User ID")
+    ),
+    responses(
+        (status = OK, description = "Success", body = AdminUserResponse),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNPROCESSABLE_ENTITY, description = "Admins cannot demote themselves"),
+        (status = FORBIDDEN, description = "Not an admin"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Admin"
+)]
+async fn edit_user(
+    Extension(state): Extension<AppState>,
+    Extension(current_user): Extension<user::Model>,
+    Path(id): Path<i32>,
+    Json(payload): Json<EditUser>,
+) -> AxumResult<Json<AdminUserResponse>> {
+    let user = user::Entity::find_by_id(id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("User not found")))?;
+
+    let mut user: user::ActiveModel = user.into();
+
+    if let Some(role) = payload.role {
+        // Keeps the instance from ending up without anyone able to undo it
+        if id == current_user.id && role < Role::Admin {
+            return Err(AxumError::unprocessable_entity(eyre!(
+                "You cannot remove your own admin role"
+            )));
+        }
+
+        user.role = Set(role);
+    }
+
+    let user = user.update(&state.db).await?;
+
+    Ok(Json(user.into()))
+}
+
+/// Sign user out of every session (admins only)
+#[utoipa::path(
+    method(delete),
+    path = "/{id}/sessions",
+    params(
+        ("id" = i32, Path, description = "User ID")
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Sessions revoked"),
+        (status = FORBIDDEN, description = "Not an admin"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Admin"
+)]
+async fn revoke_user_sessions(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+) -> AxumResult<StatusCode> {
+    token::Entity::delete_many()
+        .filter(token::Column::UserId.eq(id))
+        .exec(&state.db)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,78 @@
+use axum::{Extension, extract::Path, response::Response};
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use http::HeaderMap;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    entity::export::{self, ExportStatus},
+    errors::{AxumError, AxumResult, NotFoundError},
+    state::AppState,
+    util::{
+        downloads::{Download, serve_download},
+        tokens::hash_token,
+    },
+};
+
+/// The link is a secret, so neither the archive nor the URL should end up in shared caches
+const EXPORT_CACHE_CONTROL: &str = "private, no-store";
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(download_export))
+}
+
+/// Download a personal data export
+///
+/// The link comes from the response that started the export or the email sent once it's ready.
+/// Supports single byte `Range` requests, so interrupted downloads can be resumed.
+#[utoipa::path(
+    method(get),
+    path = "/{token}",
+    params(
+        ("token" = String, Path, description = "Download token")
+    ),
+    responses(
+        (status = OK, description = "ZIP archive", content_type = "application/zip"),
+        (status = PARTIAL_CONTENT, description = "The requested range", content_type = "application/zip"),
+        (status = NOT_FOUND, description = "Invalid or expired link", body = NotFoundError),
+        (status = RANGE_NOT_SATISFIABLE, description = "The range is outside the archive")
+    ),
+    tag = "Auth"
+)]
+async fn download_export(
+    Extension(state): Extension<AppState>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> AxumResult<Response> {
+    let export = export::Entity::find()
+        .filter(export::Column::DownloadTokenHash.eq(hash_token(&token)))
+        .filter(export::Column::Status.eq(ExportStatus::Ready))
+        .filter(export::Column::ExpiresAt.gt(Utc::now()))
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("Invalid or expired link")))?;
+
+    let (Some(key), Some(size)) = (&export.storage_key, export.size) else {
+        return Err(AxumError::not_found(eyre!("Invalid or expired link")));
+    };
+
+    let filename = format!(
+        "mathisi-export-{}.zip",
+        export.created_at.format("%Y-%m-%d")
+    );
+
+    serve_download(
+        state.storage.as_ref(),
+        &headers,
+        Download {
+            storage_key: key,
+            size: size as u64,
+            content_type: "application/zip",
+            filename: Some(&filename),
+            attachment: true,
+            cache_control: EXPORT_CACHE_CONTROL,
+        },
+    )
+    .await
+}
@@ -1,22 +1,52 @@
-use axum::{Extension, Json};
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use axum::{Extension, Json, extract::Query, middleware};
+use axum_valid::Valid;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QuerySelect, QueryTrait};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
-    entity::{note, user}, errors::AxumResult, middlewares::UnauthorizedError,
-    routes::api::notes::ManyNotesResponse, state::AppState,
+    entity::{follow, note, token::Scope, user},
+    errors::AxumResult,
+    middlewares::{ScopeRule, UnauthorizedError, require_scope},
+    routes::api::notes::{ManyNotesResponse, paginate_notes},
+    state::AppState,
+    util::{blocks::visible_authors, pagination::PageQuery, tags::TagFilter},
 };
 
 pub fn routes() -> OpenApiRouter<AppState> {
-    OpenApiRouter::new().routes(routes!(get_feed))
+    OpenApiRouter::new()
+        .routes(routes!(get_feed))
+        .layer(middleware::from_fn_with_state(
+            ScopeRule::read_only(Scope::NotesRead),
+            require_scope,
+        ))
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedScope {
+    /// Every public note on the instance
+    #[default]
+    All,
+    /// Only public notes by people you follow
+    Following,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct FeedQuery {
+    #[serde(default)]
+    pub scope: FeedScope,
 }
 
 /// Get public notes for your feed
 #[utoipa::path(
     method(get),
     path = "/",
+    params(FeedQuery, TagFilter, PageQuery),
     responses(
         (status = OK, description = "Success", body = ManyNotesResponse),
+        (status = BAD_REQUEST, description = "Invalid tag name"),
         (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
     ),
     tag = "Home"
@@ -24,13 +54,31 @@ pub fn routes() -> OpenApiRouter<AppState> {
 async fn get_feed(
     Extension(state): Extension<AppState>,
     Extension(user): Extension<user::Model>,
+    Query(query): Query<FeedQuery>,
+    Valid(Query(tags)): Valid<Query<TagFilter>>,
+    Valid(Query(page)): Valid<Query<PageQuery>>,
 ) -> AxumResult<Json<ManyNotesResponse>> {
-    let notes = note::Entity::find()
+    let mut select = note::Entity::find()
         .filter(note::Column::Public.eq(true))
-        .order_by_desc(note::Column::CreatedAt)
-        .all(&state.db)
-        .await?;
+        .filter(note::Column::HiddenAt.is_null())
+        .filter(note::Column::DeletedAt.is_null())
+        .filter(visible_authors(user.id));
+
+    if let FeedScope::Following = query.scope {
+        let followees = follow::Entity::find()
+            .select_only()
+            .column(follow::Column::FolloweeId)
+            .filter(follow::Column::FollowerId.eq(user.id))
+            .into_query();
+        select = select.filter(note::Column::UserId.in_subquery(followees));
+    }
+
+    if let Some(tagged) = tags.condition()? {
+        select = select.filter(tagged);
+    }
+
+    let notes = paginate_notes(&state.db, select, &page).await?;
     Ok(Json(
-        ManyNotesResponse::response_from_array(notes, &state.db,user.id).await?,
+        ManyNotesResponse::response_from_page(notes, &state.db, user.id).await?,
     ))
 }
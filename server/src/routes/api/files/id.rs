@@ -1,23 +1,31 @@
-use axum::{Extension, Json, extract::Path};
-use color_eyre::eyre::eyre;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, EntityTrait};
+use axum::{Extension, Json, extract::Path, response::Response};
+use http::HeaderMap;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set};
 use serde::Deserialize;
 use utoipa::ToSchema;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
     entity::{file, user},
-    errors::{AxumError, AxumResult, NotFoundError},
+    errors::{AxumResult, NotFoundError},
     middlewares::UnauthorizedError,
-    routes::api::files::UploadedFile,
+    policy::{Action, authorize},
+    routes::api::files::{UploadedFile, find_readable_file},
     state::AppState,
+    util::downloads::{Download, serve_download},
 };
 
 pub fn routes() -> OpenApiRouter<AppState> {
     OpenApiRouter::new().routes(routes!(get_file, edit_file))
 }
 
+/// A file's contents never change, so clients can keep them
+const FILE_CACHE_CONTROL: &str = "private, max-age=31536000, immutable";
+
 /// Get file contents
+///
+/// Works for your own files and for files attached to public notes you can see. Supports single
+/// byte `Range` requests and `If-None-Match` with the returned `ETag`.
 #[utoipa::path(
     method(get),
     path = "/",
@@ -26,20 +34,35 @@ pub fn routes() -> OpenApiRouter<AppState> {
     ),
     responses(
         (status = OK, description = "Success", content_type = "application/octet-stream"),
+        (status = PARTIAL_CONTENT, description = "The requested range", content_type = "application/octet-stream"),
+        (status = NOT_MODIFIED, description = "Your cached copy is current"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = RANGE_NOT_SATISFIABLE, description = "The range is outside the file"),
         (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
     ),
     tag = "Files"
 )]
 async fn get_file(
     Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
     Path(id): Path<i32>,
-) -> AxumResult<Vec<u8>> {
-    let file = file::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("File not found")))?;
+    headers: HeaderMap,
+) -> AxumResult<Response> {
+    let file = find_readable_file(&state, &user, id).await?;
 
-    Ok(file.data)
+    serve_download(
+        state.storage.as_ref(),
+        &headers,
+        Download {
+            storage_key: &file.storage_key,
+            size: file.size as u64,
+            content_type: &file.content_type,
+            filename: Some(&file.filename),
+            attachment: false,
+            cache_control: FILE_CACHE_CONTROL,
+        },
+    )
+    .await
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -68,16 +91,9 @@ async fn edit_file(
     Path(id): Path<i32>,
     Json(payload): Json<EditFile>,
 ) -> AxumResult<Json<UploadedFile>> {
-    let file = file::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("File not found")))?;
+    let file = find_readable_file(&state, &user, id).await?;
 
-    if file.user_id != user.id {
-        return Err(AxumError::unauthorized(eyre!(
-            "You do not have permission to edit this file"
-        )));
-    }
+    authorize(&user, &file, Action::Edit)?;
 
     let mut file: file::ActiveModel = file.into();
 
@@ -3,21 +3,27 @@ mod id;
 use axum::{
     Extension, Json,
     extract::{DefaultBodyLimit, Multipart},
+    middleware,
 };
 use chrono::Utc;
 use color_eyre::eyre::eyre;
 use infer::is_image;
 use sanitize_filename::sanitize;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter,
+};
 use serde::Serialize;
 use utoipa::ToSchema;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
-    entity::{file, user},
+    entity::{file, note, note_files, token::Scope, user},
     errors::{AxumError, AxumResult},
-    middlewares::UnauthorizedError,
+    middlewares::{ScopeRule, UnauthorizedError, require_scope},
+    policy::{Action, Policy},
     state::AppState,
+    storage::{FILES_PREFIX, new_key, sniff_content_type},
+    util::blocks::not_blocked_by,
 };
 
 pub fn routes() -> OpenApiRouter<AppState> {
@@ -25,6 +31,45 @@ pub fn routes() -> OpenApiRouter<AppState> {
         .routes(routes!(upload_files))
         .layer(DefaultBodyLimit::max(1024 * 1024 * 10)) // 10 MB
         .nest("/{id}", id::routes())
+        .layer(middleware::from_fn_with_state(
+            ScopeRule::new(Scope::FilesRead, Scope::FilesWrite),
+            require_scope,
+        ))
+}
+
+/// Finds a file `user` may download: their own, or one attached to a public note they can see.
+/// Other files look like they don't exist.
+pub async fn find_readable_file(
+    state: &AppState,
+    user: &user::Model,
+    id: i32,
+) -> AxumResult<file::Model> {
+    let not_found = || AxumError::not_found(eyre!("File not found"));
+
+    let file = file::Entity::find_by_id(id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(not_found)?;
+
+    if file.allows(user, Action::Read) {
+        return Ok(file);
+    }
+
+    let visible_notes = note_files::Entity::find()
+        .inner_join(note::Entity)
+        .filter(note_files::Column::FileId.eq(file.id))
+        .filter(note::Column::Public.eq(true))
+        .filter(note::Column::HiddenAt.is_null())
+        .filter(note::Column::DeletedAt.is_null())
+        .filter(not_blocked_by(user.id))
+        .count(&state.db)
+        .await?;
+
+    if visible_notes == 0 {
+        return Err(not_found());
+    }
+
+    Ok(file)
 }
 
 #[derive(Serialize, ToSchema)]
@@ -76,11 +121,18 @@ async fn upload_files(
             )));
         }
 
+        let storage_key = new_key(FILES_PREFIX);
+        let size = bytes.len() as i64;
+        let content_type = sniff_content_type(&bytes).to_string();
+        state.storage.put(&storage_key, bytes).await?;
+
         let file = file::ActiveModel {
             user_id: Set(user.id),
             created_at: Set(Utc::now()),
             filename: Set(filename),
-            data: Set(bytes.to_vec()),
+            storage_key: Set(storage_key),
+            size: Set(size),
+            content_type: Set(content_type),
             ..Default::default()
         };
 
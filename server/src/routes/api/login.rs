@@ -1,10 +1,11 @@
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use axum::{Extension, Json};
 use axum_valid::Valid;
-use chrono::Utc;
+use chrono::{DateTime, TimeDelta, Utc};
 use color_eyre::eyre::eyre;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set};
+use redis::AsyncCommands;
+use sea_orm::{ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 use utoipa::ToSchema;
 use utoipa_axum::{router::OpenApiRouter, routes};
 use validator::Validate;
@@ -12,12 +13,25 @@ use validator::Validate;
 use crate::{
     entity::{token, user},
     errors::{AxumError, AxumResult},
+    middlewares::{ClientIp, check_login_lockout, clear_login_failures, record_login_failure},
     state::AppState,
-    util::tokens::{generate_token, hash_token},
+    util::{
+        passwords::verify_password,
+        sessions::start_session,
+        tokens::{TokenPair, generate_token, hash_token},
+        two_factor::verify_second_factor,
+    },
 };
 
 pub fn routes() -> OpenApiRouter<AppState> {
-    OpenApiRouter::new().routes(routes!(login))
+    OpenApiRouter::new()
+        .routes(routes!(login))
+        .routes(routes!(login_two_factor))
+}
+
+/// Refreshing is routine rather than a login attempt, so it isn't held to the auth rate limit
+pub fn refresh_routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(refresh))
 }
 
 #[derive(Deserialize, ToSchema, Validate)]
@@ -32,56 +46,246 @@ pub struct LoginRequest {
 #[derive(Serialize, ToSchema)]
 pub struct LoginResponse {
     pub token: String,
+    pub expires_at: DateTime<Utc>,
+    pub refresh_token: String,
+    pub refresh_expires_at: DateTime<Utc>,
+}
+
+impl From<TokenPair> for LoginResponse {
+    fn from(pair: TokenPair) -> Self {
+        LoginResponse {
+            token: pair.access_token,
+            expires_at: pair.expires_at,
+            refresh_token: pair.refresh_token,
+            refresh_expires_at: pair.refresh_expires_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TwoFactorChallengeResponse {
+    /// Always `true`, tells this response apart from a finished login
+    pub two_factor_required: bool,
+
+    /// Exchanged together with a TOTP or recovery code at `/api/login/2fa`
+    pub challenge_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum LoginResult {
+    Session(LoginResponse),
+    TwoFactorRequired(TwoFactorChallengeResponse),
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct TwoFactorLoginRequest {
+    #[validate(length(min = 1, max = 128))]
+    pub challenge_token: String,
+
+    /// TOTP code or recovery code
+    #[validate(length(min = 1, max = 32))]
+    pub code: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct RefreshRequest {
+    #[validate(length(min = 1, max = 128))]
+    pub refresh_token: String,
 }
 
 /// Log in
+///
+/// Accounts with two-factor authentication get a challenge token instead, to be completed at
+/// `/api/login/2fa`
 #[utoipa::path(
     method(post),
     path = "/",
     responses(
-        (status = OK, description = "Success", body = LoginResponse)
+        (status = OK, description = "Success", body = LoginResult),
+        (status = TOO_MANY_REQUESTS, description = "Too many failed login attempts")
     ),
     tag = "Auth"
 )]
 async fn login(
     Extension(state): Extension<AppState>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
     Valid(Json(body)): Valid<Json<LoginRequest>>,
-) -> AxumResult<Json<LoginResponse>> {
+) -> AxumResult<Json<LoginResult>> {
+    check_login_lockout(&state, &body.username, ip).await?;
+
     // TODO: Validate user
     let user = user::Entity::find_by_username(body.username.clone())
         .one(&state.db)
         .await?;
     let Some(user) = user else {
+        record_login_failure(&state, &body.username, ip).await;
         return Err(AxumError::unauthorized(eyre!(
             "Invalid username or password"
         )));
     };
 
-    let argon2 = Argon2::default();
-    let hash = PasswordHash::new(&user.password)
-        .map_err(|_| AxumError::unauthorized(eyre!("Invalid username or password")))?;
-
-    let is_valid = argon2
-        .verify_password(body.password.as_bytes(), &hash)
-        .is_ok();
-
-    if !is_valid {
+    if !verify_password(&body.password, &user.password) {
+        record_login_failure(&state, &body.username, ip).await;
         return Err(AxumError::unauthorized(eyre!(
             "Invalid username or password"
         )));
     }
 
-    let token = generate_token();
-    let hashed_token = hash_token(&token);
+    // Failures keep counting until the second step, so a known password can't be used to reset
+    // the lockout between guesses at the code
+    if user.totp_enabled_at.is_some() {
+        let challenge = start_two_factor_challenge(&state, &user).await?;
+        return Ok(Json(LoginResult::TwoFactorRequired(challenge)));
+    }
+
+    clear_login_failures(&state, &body.username, ip).await;
+
+    let pair = start_session(&state, user.id).await?;
+
+    Ok(Json(LoginResult::Session(pair.into())))
+}
+
+/// Remembers that `user` got past the first step, until they finish at `/api/login/2fa`
+pub async fn start_two_factor_challenge(
+    state: &AppState,
+    user: &user::Model,
+) -> AxumResult<TwoFactorChallengeResponse> {
+    let challenge_token = generate_token();
+    let lifetime = state.settings.auth.two_factor_challenge_lifetime;
+
+    let mut redis = state.redis.clone();
+    redis
+        .set_ex::<_, _, ()>(challenge_key(&challenge_token), user.id, lifetime)
+        .await?;
+
+    Ok(TwoFactorChallengeResponse {
+        two_factor_required: true,
+        challenge_token,
+        expires_at: Utc::now() + TimeDelta::seconds(lifetime as i64),
+    })
+}
+
+fn challenge_key(challenge_token: &str) -> String {
+    format!("2fa:challenge:{}", hash_token(challenge_token))
+}
+
+/// Finish logging in with a two-factor code
+#[utoipa::path(
+    method(post),
+    path = "/2fa",
+    responses(
+        (status = OK, description = "Success", body = LoginResponse),
+        (status = UNAUTHORIZED, description = "Invalid or expired challenge, or invalid code"),
+        (status = TOO_MANY_REQUESTS, description = "Too many failed login attempts")
+    ),
+    tag = "Auth"
+)]
+async fn login_two_factor(
+    Extension(state): Extension<AppState>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Valid(Json(body)): Valid<Json<TwoFactorLoginRequest>>,
+) -> AxumResult<Json<LoginResponse>> {
+    let key = challenge_key(&body.challenge_token);
+
+    let mut redis = state.redis.clone();
+    let user_id: Option<i32> = redis.get(&key).await?;
+
+    let user = match user_id {
+        Some(user_id) => user::Entity::find_by_id(user_id).one(&state.db).await?,
+        None => None,
+    }
+    .ok_or_else(|| {
+        AxumError::unauthorized(eyre!("Invalid or expired login attempt"))
+            .with_code("challenge_expired")
+    })?;
+
+    check_login_lockout(&state, &user.username, ip).await?;
+
+    if !verify_second_factor(&state, &user, &body.code).await? {
+        record_login_failure(&state, &user.username, ip).await;
+        return Err(AxumError::unauthorized(eyre!("Invalid code")).with_code("invalid_code"));
+    }
+
+    redis.del::<_, ()>(&key).await?;
+    clear_login_failures(&state, &user.username, ip).await;
+
+    let pair = start_session(&state, user.id).await?;
+
+    Ok(Json(pair.into()))
+}
 
-    let token_entry = token::ActiveModel {
-        user_id: Set(user.id),
-        token_hash: Set(hashed_token),
-        created_at: Set(Utc::now()),
-        ..Default::default()
+/// Refresh access token
+///
+/// Exchanges a refresh token for a new token pair. The old access and refresh tokens stop working.
+/// Using a refresh token again after it was exchanged ends the session.
+#[utoipa::path(
+    method(post),
+    path = "/refresh",
+    responses(
+        (status = OK, description = "Success", body = LoginResponse),
+        (status = UNAUTHORIZED, description = "Invalid, expired or reused refresh token")
+    ),
+    tag = "Auth"
+)]
+async fn refresh(
+    Extension(state): Extension<AppState>,
+    Valid(Json(body)): Valid<Json<RefreshRequest>>,
+) -> AxumResult<Json<LoginResponse>> {
+    let refresh_token_hash = hash_token(&body.refresh_token);
+
+    let Some(token) = token::Entity::find()
+        .filter(token::Column::RefreshTokenHash.eq(&refresh_token_hash))
+        .one(&state.db)
+        .await?
+    else {
+        // Either the client or someone who stole the token already exchanged it. There's no
+        // telling which, so nobody keeps the session.
+        let revoked = token::Entity::delete_many()
+            .filter(token::Column::PreviousRefreshTokenHash.eq(&refresh_token_hash))
+            .exec(&state.db)
+            .await?;
+        if revoked.rows_affected > 0 {
+            warn!("Revoked a session after its refresh token was reused");
+            return Err(AxumError::unauthorized(eyre!("Refresh token reused"))
+                .with_code("refresh_token_reused"));
+        }
+
+        return Err(AxumError::unauthorized(eyre!("Invalid refresh token")));
     };
 
-    token_entry.insert(&state.db).await?;
+    let now = Utc::now();
+
+    if token
+        .refresh_expires_at
+        .is_none_or(|expires_at| expires_at <= now)
+    {
+        return Err(AxumError::unauthorized(eyre!("Refresh token expired"))
+            .with_code("refresh_token_expired"));
+    }
+
+    let pair = TokenPair::generate(&state.settings.auth);
+
+    // Only one of several concurrent refreshes with the same token may win
+    let rotated = token::Entity::update_many()
+        .set(token::ActiveModel {
+            token_hash: Set(hash_token(&pair.access_token)),
+            expires_at: Set(Some(pair.expires_at)),
+            last_used_at: Set(now),
+            refresh_token_hash: Set(Some(hash_token(&pair.refresh_token))),
+            refresh_expires_at: Set(Some(pair.refresh_expires_at)),
+            previous_refresh_token_hash: Set(Some(refresh_token_hash.clone())),
+            ..Default::default()
+        })
+        .filter(token::Column::Id.eq(token.id))
+        .filter(token::Column::RefreshTokenHash.eq(&refresh_token_hash))
+        .exec(&state.db)
+        .await?;
+
+    if rotated.rows_affected != 1 {
+        return Err(AxumError::unauthorized(eyre!("Invalid refresh token")));
+    }
 
-    Ok(Json(LoginResponse { token }))
+    Ok(Json(pair.into()))
 }
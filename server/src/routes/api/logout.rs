@@ -0,0 +1,32 @@
+use axum::Extension;
+use http::StatusCode;
+use sea_orm::ModelTrait;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{entity::token, errors::AxumResult, middlewares::UnauthorizedError, state::AppState};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(logout))
+}
+
+/// Log out
+///
+/// Revokes the token used to authenticate this request. Personal access tokens can revoke
+/// themselves whatever their scopes, since that only ever takes access away.
+#[utoipa::path(
+    method(post),
+    path = "/",
+    responses(
+        (status = NO_CONTENT, description = "Token revoked"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn logout(
+    Extension(state): Extension<AppState>,
+    Extension(token): Extension<token::Model>,
+) -> AxumResult<StatusCode> {
+    token.delete(&state.db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
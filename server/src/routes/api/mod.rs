@@ -1,14 +1,23 @@
+mod admin;
+mod exports;
 mod feed;
 mod files;
 mod login;
+mod logout;
 mod notes;
+mod oidc;
+mod password;
 mod register;
+mod tags;
 mod user;
 
 use axum::middleware;
 use utoipa_axum::router::OpenApiRouter;
 
-use crate::{middlewares::with_auth, state::AppState};
+use crate::{
+    middlewares::{RateLimitGroup, rate_limit, with_auth},
+    state::AppState,
+};
 
 pub fn routes() -> OpenApiRouter<AppState> {
     let auth = OpenApiRouter::new()
@@ -16,11 +25,32 @@ pub fn routes() -> OpenApiRouter<AppState> {
         .nest("/notes", notes::routes())
         .nest("/files", files::routes())
         .nest("/feed", feed::routes())
+        .nest("/tags", tags::routes())
+        .nest("/logout", logout::routes())
+        .nest("/admin", admin::routes())
+        .layer(middleware::from_fn_with_state(
+            RateLimitGroup::Api,
+            rate_limit,
+        ))
         .layer(middleware::from_fn(with_auth));
 
     let public = OpenApiRouter::new()
         .nest("/login", login::routes())
-        .nest("/register", register::routes());
+        .nest("/register", register::routes())
+        .nest("/password", password::routes())
+        .nest("/oidc", oidc::routes())
+        .nest("/exports", exports::routes())
+        .layer(middleware::from_fn_with_state(
+            RateLimitGroup::Auth,
+            rate_limit,
+        ));
 
-    auth.merge(public)
+    let refresh = OpenApiRouter::new()
+        .nest("/login", login::refresh_routes())
+        .layer(middleware::from_fn_with_state(
+            RateLimitGroup::Api,
+            rate_limit,
+        ));
+
+    auth.merge(public).merge(refresh)
 }
@@ -10,7 +10,9 @@ use axum_valid::Valid;
 use base64::{Engine as _, engine::general_purpose};
 use chrono::Utc;
 use color_eyre::eyre::eyre;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, TransactionTrait,
+};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use utoipa_axum::{router::OpenApiRouter, routes};
@@ -18,10 +20,14 @@ use validator::Validate;
 
 use crate::{
     entity::{file, note, user},
-    errors::{AxumError, AxumResult},
+    errors::{AxumError, AxumResult, NotFoundError},
     middlewares::UnauthorizedError,
-    routes::api::notes::NoteCreateResponse,
     state::AppState,
+    util::{
+        attachments::{attach_files, own_files},
+        revisions::record_revision,
+        verification::require_verified,
+    },
 };
 
 pub fn routes() -> OpenApiRouter<AppState> {
@@ -32,6 +38,7 @@ pub fn routes() -> OpenApiRouter<AppState> {
 pub struct AiNoteCreateRequest {
     pub title: String,
     pub prompt: String,
+    /// IDs of your own uploaded images, attached to the note afterwards
     pub files: Vec<i32>,
     pub public: Option<bool>,
 }
@@ -44,6 +51,7 @@ pub struct AiNoteCreateRequest {
     path = "/",
     responses(
         (status = OK, description = "Success", body = AiNoteCreateResponse),
+        (status = NOT_FOUND, description = "File not found", body = NotFoundError),
         (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
     ),
     tag = "Notes"
@@ -53,8 +61,15 @@ pub async fn create_ai_note(
     Extension(user): Extension<user::Model>,
     Valid(Json(body)): Valid<Json<AiNoteCreateRequest>>,
 ) -> AxumResult<Json<AiNoteCreateResponse>> {
+    require_verified(&user, state.settings.auth.verify_before_ai)?;
+
+    if body.public == Some(true) {
+        require_verified(&user, state.settings.auth.verify_before_publishing)?;
+    }
+
+    let file_ids = own_files(&state.db, user.id, &body.files).await?;
     let files = file::Entity::find()
-        .filter(file::Column::Id.is_in(body.files))
+        .filter(file::Column::Id.is_in(file_ids.clone()))
         .all(&state.db)
         .await?;
 
@@ -79,7 +94,8 @@ pub async fn create_ai_note(
             "image/jpeg"
         };
 
-        let base64_data = general_purpose::STANDARD.encode(&file.data);
+        let contents = state.storage.read(&file.storage_key).await?;
+        let base64_data = general_purpose::STANDARD.encode(contents);
 
         let data_url = format!("data:{};base64,{}", mime_type, base64_data);
 
@@ -131,7 +147,12 @@ pub async fn create_ai_note(
         ..Default::default()
     };
 
-    let inserted = model.insert(&state.db).await?;
+    let txn = state.db.begin().await?;
+    let inserted = model.insert(&txn).await?;
+    record_revision(&txn, &inserted, user.id).await?;
+    // Keeps the source pages next to the generated note
+    attach_files(&txn, inserted.id, &file_ids).await?;
+    txn.commit().await?;
 
     Ok(Json(AiNoteCreateResponse {
         content: ai_content.to_owned(),
@@ -0,0 +1,136 @@
+use axum::{Extension, Json, extract::Path};
+use axum_valid::Valid;
+use http::StatusCode;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
+
+use crate::{
+    entity::{note_files, user},
+    errors::{AxumResult, NotFoundError},
+    middlewares::UnauthorizedError,
+    policy::{Action, authorize},
+    routes::api::notes::{AttachmentResponse, find_note, find_readable_note, load_attachments},
+    state::AppState,
+    util::attachments::{attach_files, own_files},
+};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_note_files, attach_note_files))
+        .routes(routes!(detach_note_file))
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct AttachFilesRequest {
+    /// IDs of your own uploaded files
+    #[validate(length(min = 1, max = 50))]
+    pub files: Vec<i32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ManyAttachmentsResponse {
+    pub files: Vec<AttachmentResponse>,
+}
+
+/// Get files attached to a note
+#[utoipa::path(
+    method(get),
+    path = "/files",
+    params(
+        ("id" = i32, Path, description = "Note ID")
+    ),
+    responses(
+        (status = OK, description = "Success", body = ManyAttachmentsResponse),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn get_note_files(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<Json<ManyAttachmentsResponse>> {
+    let note = find_readable_note(&state, &user, id).await?;
+
+    Ok(Json(attachments(&state, note.id).await?))
+}
+
+/// Attach files to a note
+///
+/// Only your own uploads can be attached. Files already attached are skipped.
+#[utoipa::path(
+    method(post),
+    path = "/files",
+    params(
+        ("id" = i32, Path, description = "Note ID")
+    ),
+    request_body = AttachFilesRequest,
+    responses(
+        (status = OK, description = "Success", body = ManyAttachmentsResponse),
+        (status = FORBIDDEN, description = "Not your note"),
+        (status = NOT_FOUND, description = "Note or file not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn attach_note_files(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+    Valid(Json(body)): Valid<Json<AttachFilesRequest>>,
+) -> AxumResult<Json<ManyAttachmentsResponse>> {
+    let note = find_note(&state.db, id).await?;
+    authorize(&user, &note, Action::Edit)?;
+
+    let file_ids = own_files(&state.db, user.id, &body.files).await?;
+    attach_files(&state.db, note.id, &file_ids).await?;
+
+    Ok(Json(attachments(&state, note.id).await?))
+}
+
+/// Detach a file from a note
+///
+/// The file itself is kept.
+#[utoipa::path(
+    method(delete),
+    path = "/files/{file_id}",
+    params(
+        ("id" = i32, Path, description = "Note ID"),
+        ("file_id" = i32, Path, description = "File ID")
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Detached"),
+        (status = FORBIDDEN, description = "Not your note"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn detach_note_file(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path((id, file_id)): Path<(i32, i32)>,
+) -> AxumResult<StatusCode> {
+    let note = find_note(&state.db, id).await?;
+    authorize(&user, &note, Action::Edit)?;
+
+    note_files::Entity::delete_many()
+        .filter(note_files::Column::NoteId.eq(note.id))
+        .filter(note_files::Column::FileId.eq(file_id))
+        .exec(&state.db)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn attachments(state: &AppState, note_id: i32) -> AxumResult<ManyAttachmentsResponse> {
+    let mut files = load_attachments(&state.db, &[note_id]).await?;
+
+    Ok(ManyAttachmentsResponse {
+        files: files.remove(&note_id).unwrap_or_default(),
+    })
+}
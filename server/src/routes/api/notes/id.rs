@@ -1,7 +1,10 @@
 use axum::{Extension, Json, extract::Path};
+use chrono::Utc;
 use color_eyre::eyre::eyre;
+use http::StatusCode;
 use sea_orm::{
     ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, ModelTrait, QueryFilter,
+    TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
@@ -9,22 +12,30 @@ use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
     entity::{note, save, upvote, user},
-    errors::{AxumError, AxumResult},
+    errors::{AxumError, AxumResult, NotFoundError},
     middlewares::UnauthorizedError,
-    routes::api::notes::NoteResponse,
+    policy::{Action, authorize},
+    routes::api::notes::{NoteResponse, find_note, find_readable_note},
     state::AppState,
+    util::{
+        revisions::{ensure_initial_revision, record_revision},
+        verification::require_verified,
+    },
 };
 
 pub fn routes() -> OpenApiRouter<AppState> {
     OpenApiRouter::new()
         .routes(routes!(get_note))
         .routes(routes!(edit_note))
+        .routes(routes!(delete_note))
+        .routes(routes!(restore_note))
         .routes(routes!(bookmark_note))
         .routes(routes!(is_bookmark_on_note))
         .routes(routes!(upvote_note))
         .routes(routes!(downvote_note))
         .routes(routes!(get_note_votes))
         .routes(routes!(get_note_vote))
+        .routes(routes!(hide_note, unhide_note))
 }
 
 /// Get single note
@@ -45,10 +56,7 @@ async fn get_note(
     Extension(user): Extension<user::Model>,
     Path(id): Path<i32>,
 ) -> AxumResult<Json<NoteResponse>> {
-    let note = note::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))?;
+    let note = find_readable_note(&state, &user, id).await?;
 
     Ok(Json(note.to_response(&state.db, user.id, false).await?))
 }
@@ -96,17 +104,15 @@ async fn edit_note(
     Path(id): Path<i32>,
     Json(payload): Json<EditNote>,
 ) -> AxumResult<Json<NoteResponse>> {
-    let note = note::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))?;
+    let note = find_note(&state.db, id).await?;
 
-    if note.user_id != user.id {
-        return Err(AxumError::unauthorized(eyre!(
-            "You do not have permission to edit this note"
-        )));
+    authorize(&user, &note, Action::Edit)?;
+
+    if payload.public == Some(true) {
+        require_verified(&user, state.settings.auth.verify_before_publishing)?;
     }
 
+    let previous = note.clone();
     let mut note: note::ActiveModel = note.into();
 
     if let Some(content) = payload.content {
@@ -121,6 +127,84 @@ async fn edit_note(
         note.public = Set(public);
     }
 
+    let txn = state.db.begin().await?;
+    let note = note.update(&txn).await?;
+
+    let changed = note.title != previous.title
+        || note.content != previous.content
+        || note.public != previous.public;
+    if changed {
+        ensure_initial_revision(&txn, &previous).await?;
+        record_revision(&txn, &note, user.id).await?;
+    }
+    txn.commit().await?;
+
+    Ok(Json(note.to_response(&state.db, user.id, false).await?))
+}
+
+/// Delete note
+///
+/// Moves the note to the trash, where it can be restored until it's purged for good. It
+/// disappears from every list, search and feed right away.
+#[utoipa::path(
+    method(delete),
+    path = "/",
+    params(
+        ("id" = i32, Path, description = "Note ID")
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Moved to the trash"),
+        (status = FORBIDDEN, description = "Not your note"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn delete_note(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<StatusCode> {
+    let note = find_note(&state.db, id).await?;
+
+    authorize(&user, &note, Action::Edit)?;
+
+    let mut note: note::ActiveModel = note.into();
+    note.deleted_at = Set(Some(Utc::now()));
+    note.update(&state.db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Restore note from the trash
+#[utoipa::path(
+    method(post),
+    path = "/restore",
+    params(
+        ("id" = i32, Path, description = "Note ID")
+    ),
+    responses(
+        (status = OK, description = "Success", body = NoteResponse),
+        (status = FORBIDDEN, description = "Not your note"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn restore_note(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<Json<NoteResponse>> {
+    let note = note::Entity::find_by_id(id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))?;
+
+    authorize(&user, &note, Action::Edit)?;
+
+    let mut note: note::ActiveModel = note.into();
+    note.deleted_at = Set(None);
     let note = note.update(&state.db).await?;
 
     Ok(Json(note.to_response(&state.db, user.id, false).await?))
@@ -143,11 +227,7 @@ async fn is_bookmark_on_note(
     Extension(user): Extension<user::Model>,
     Path(id): Path<i32>,
 ) -> AxumResult<Json<NoteBookmarkResponse>> {
-    // Ensure note exists
-    note::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))?;
+    find_readable_note(&state, &user, id).await?;
 
     // Check if a save exists
     let is_bookmarked = save::Entity::find()
@@ -182,11 +262,7 @@ async fn bookmark_note(
     Path(id): Path<i32>,
 ) -> AxumResult<Json<NoteBookmarkResponse>> {
     let mut created: bool = false;
-    // Ensure note exists
-    let note = note::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))?;
+    find_readable_note(&state, &user, id).await?;
 
     // Check if a save already exists
     if let Some(existing_save) = save::Entity::find()
@@ -234,10 +310,7 @@ async fn upvote_note(
     Path(id): Path<i32>,
 ) -> AxumResult<Json<NoteUpvoteResponse>> {
     let mut value: i32 = 0;
-    let note = note::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))?;
+    find_readable_note(&state, &user, id).await?;
 
     if let Some(existing_save) = upvote::Entity::find()
         .filter(upvote::Column::UserId.eq(user.id))
@@ -283,10 +356,7 @@ async fn downvote_note(
     Path(id): Path<i32>,
 ) -> AxumResult<Json<NoteUpvoteResponse>> {
     let mut value: i32 = 0;
-    let note = note::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))?;
+    find_readable_note(&state, &user, id).await?;
 
     if let Some(existing_save) = upvote::Entity::find()
         .filter(upvote::Column::UserId.eq(user.id))
@@ -330,22 +400,12 @@ async fn get_note_votes(
     Extension(user): Extension<user::Model>,
     Path(id): Path<i32>,
 ) -> AxumResult<Json<NoteVotesResponse>> {
-    let note = note::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))?;
-
-    let votes = upvote::Entity::find()
-        .filter(upvote::Column::NoteId.eq(id))
-        .all(&state.db)
-        .await?
-        .into_iter()
-        .map(|v| if v.is_upvote { 1 } else { -1 })
-        .sum::<i32>();
+    let note = find_readable_note(&state, &user, id).await?;
+    let votes = note.to_response(&state.db, user.id, true).await?.votes;
 
     Ok(Json(NoteVotesResponse {
         success: true,
-        votes: votes,
+        votes,
     }))
 }
 
@@ -366,10 +426,7 @@ async fn get_note_vote(
     Extension(user): Extension<user::Model>,
     Path(id): Path<i32>,
 ) -> AxumResult<Json<NoteUpvoteResponse>> {
-    let note = note::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))?;
+    find_readable_note(&state, &user, id).await?;
 
     let votes = upvote::Entity::find()
         .filter(upvote::Column::NoteId.eq(id))
@@ -385,3 +442,64 @@ async fn get_note_vote(
         is_upvoted: votes,
     }))
 }
+
+/// Hide note from everyone but its owner (moderators only)
+#[utoipa::path(
+    method(post),
+    path = "/hide",
+    params(
+        ("id" = i32, Path, description = "Note ID")
+    ),
+    responses(
+        (status = OK, description = "Success", body = NoteResponse),
+        (status = FORBIDDEN, description = "Not a moderator"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Moderation"
+)]
+async fn hide_note(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<Json<NoteResponse>> {
+    set_note_hidden(&state, &user, id, true).await
+}
+
+/// Unhide note (moderators only)
+#[utoipa::path(
+    method(delete),
+    path = "/hide",
+    params(
+        ("id" = i32, Path, description = "Note ID")
+    ),
+    responses(
+        (status = OK, description = "Success", body = NoteResponse),
+        (status = FORBIDDEN, description = "Not a moderator"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Moderation"
+)]
+async fn unhide_note(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<Json<NoteResponse>> {
+    set_note_hidden(&state, &user, id, false).await
+}
+
+async fn set_note_hidden(
+    state: &AppState,
+    user: &user::Model,
+    id: i32,
+    hidden: bool,
+) -> AxumResult<Json<NoteResponse>> {
+    let note = find_note(&state.db, id).await?;
+
+    authorize(user, &note, Action::Moderate)?;
+
+    let mut note: note::ActiveModel = note.into();
+    note.hidden_at = Set(hidden.then(Utc::now));
+    let note = note.update(&state.db).await?;
+
+    Ok(Json(note.to_response(&state.db, user.id, false).await?))
+}
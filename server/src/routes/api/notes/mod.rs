@@ -1,14 +1,20 @@
 mod ai;
+mod files;
 mod id;
 mod quiz;
+mod revisions;
+mod search;
+mod tags;
 
-use axum::{Extension, Json};
+use std::collections::HashMap;
+
+use axum::{Extension, Json, extract::Query, middleware};
 use axum_valid::Valid;
-use chrono::{DateTime, Utc};
-use color_eyre::eyre::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+use color_eyre::eyre::{Result, eyre};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait,
-    PaginatorTrait, QueryFilter, QueryOrder,
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, Select, TransactionTrait, sea_query::Expr,
 };
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
@@ -16,89 +22,272 @@ use utoipa_axum::{router::OpenApiRouter, routes};
 use validator::Validate;
 
 use crate::{
-    entity::{note, save, upvote, user},
-    errors::AxumResult,
-    middlewares::UnauthorizedError,
+    entity::{
+        file, note, note_files, save,
+        token::Scope,
+        upvote,
+        user::{self, Role},
+    },
+    errors::{AxumError, AxumResult},
+    middlewares::{RateLimitGroup, ScopeRule, UnauthorizedError, rate_limit, require_scope},
+    policy::{Action, Policy},
     state::AppState,
+    util::{
+        blocks::{has_blocked, not_blocked_by, visible_authors},
+        pagination::{Page, PageQuery, paginate},
+        revisions::record_revision,
+        tags::{TagFilter, load_tag_names},
+        verification::require_verified,
+    },
 };
 
 pub fn routes() -> OpenApiRouter<AppState> {
     OpenApiRouter::new()
         .routes(routes!(create_note, get_notes))
         .routes(routes!(get_bookmarked_notes))
-        .nest("/ai", ai::routes())
-        .nest("/{id}", id::routes().nest("/quiz", quiz::routes()))
+        .routes(routes!(get_trashed_notes))
+        .nest("/search", search::routes())
+        .nest(
+            "/{id}",
+            id::routes()
+                .merge(tags::routes())
+                .merge(files::routes())
+                .nest("/quiz", quiz::routes())
+                .nest("/revisions", revisions::routes()),
+        )
+        .layer(middleware::from_fn_with_state(
+            ScopeRule::new(Scope::NotesRead, Scope::NotesWrite),
+            require_scope,
+        ))
+        // Added after the notes scope so `ai:generate` is all it takes
+        .nest(
+            "/ai",
+            ai::routes()
+                .layer(middleware::from_fn_with_state(
+                    RateLimitGroup::Ai,
+                    rate_limit,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    ScopeRule::all(Scope::AiGenerate),
+                    require_scope,
+                )),
+        )
 }
 
-impl note::Model {
-    pub async fn to_response(
-        &self,
-        db: &DatabaseConnection,
-        user_id: i32,
-        short: bool,
-    ) -> Result<NoteResponse> {
-        let saves = self.find_related(save::Entity).count(db).await? as i32;
+/// Finds a note that isn't in the trash
+async fn find_note(db: &DatabaseConnection, id: i32) -> AxumResult<note::Model> {
+    note::Entity::find_by_id(id)
+        .filter(note::Column::DeletedAt.is_null())
+        .one(db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))
+}
 
-        let user_vote = upvote::Entity::find()
-            .filter(upvote::Column::NoteId.eq(self.id))
-            .filter(upvote::Column::UserId.eq(user_id))
-            .one(db)
-            .await?
-            .into_iter()
-            .map(|v| if v.is_upvote { 1 } else { -1 })
-            .sum::<i32>();
-
-        // Fixed: swapped the filter conditions
-        let is_bookmarked = save::Entity::find()
-            .filter(save::Column::UserId.eq(user_id))
-            .filter(save::Column::NoteId.eq(self.id))
-            .one(db)
-            .await?
-            .is_some();
-
-        let votes = upvote::Entity::find()
-            .filter(upvote::Column::NoteId.eq(self.id))
+/// Finds a note `user` may read. Notes by someone who blocked them look like they don't exist.
+async fn find_readable_note(
+    state: &AppState,
+    user: &user::Model,
+    id: i32,
+) -> AxumResult<note::Model> {
+    let note = find_note(&state.db, id).await?;
+
+    let readable = note.allows(user, Action::Read)
+        && (user.role >= Role::Moderator || !has_blocked(&state.db, note.user_id, user.id).await?);
+    if !readable {
+        return Err(AxumError::not_found(eyre!("Note not found")));
+    }
+
+    Ok(note)
+}
+
+/// Notes `user_id` can come across outside of their bookmarks: their own and the public ones by
+/// authors they didn't mute or block, leaving out the trash
+pub fn discoverable_notes(user_id: i32) -> Condition {
+    let public = Condition::all()
+        .add(note::Column::Public.eq(true))
+        .add(note::Column::HiddenAt.is_null())
+        .add(visible_authors(user_id));
+
+    Condition::all().add(note::Column::DeletedAt.is_null()).add(
+        Condition::any()
+            .add(note::Column::UserId.eq(user_id))
+            .add(public),
+    )
+}
+
+/// Votes, bookmarks, tags and attachments for a batch of notes, loaded with one query per table
+struct NoteStats {
+    /// Note ID to (vote total, the user's vote)
+    votes: HashMap<i32, (i64, i64)>,
+    /// Note ID to (bookmark count, whether the user bookmarked it)
+    saves: HashMap<i32, (i64, bool)>,
+    /// Note ID to its tag names
+    tags: HashMap<i32, Vec<String>>,
+    /// Note ID to its attached files
+    files: HashMap<i32, Vec<AttachmentResponse>>,
+}
+
+impl NoteStats {
+    async fn load(db: &DatabaseConnection, note_ids: &[i32], user_id: i32) -> Result<Self> {
+        // +1 for an upvote, -1 for a downvote
+        let vote_value = || Expr::case(upvote::Column::IsUpvote.eq(true), 1).finally(-1);
+
+        let votes: Vec<(i32, i64, i64)> = upvote::Entity::find()
+            .select_only()
+            .column(upvote::Column::NoteId)
+            .column_as(Expr::expr(vote_value()).sum(), "votes")
+            .column_as(
+                Expr::expr(Expr::case(upvote::Column::UserId.eq(user_id), vote_value()).finally(0))
+                    .sum(),
+                "user_vote",
+            )
+            .filter(upvote::Column::NoteId.is_in(note_ids.iter().copied()))
+            .group_by(upvote::Column::NoteId)
+            .into_tuple()
             .all(db)
-            .await?
-            .into_iter()
-            .map(|v| if v.is_upvote { 1 } else { -1 })
-            .sum::<i32>();
+            .await?;
+
+        let saves: Vec<(i32, i64, i64)> = save::Entity::find()
+            .select_only()
+            .column(save::Column::NoteId)
+            .column_as(save::Column::Id.count(), "saves")
+            .column_as(
+                Expr::expr(Expr::case(save::Column::UserId.eq(user_id), 1).finally(0)).sum(),
+                "user_saves",
+            )
+            .filter(save::Column::NoteId.is_in(note_ids.iter().copied()))
+            .group_by(save::Column::NoteId)
+            .into_tuple()
+            .all(db)
+            .await?;
+
+        let tags = load_tag_names(db, note_ids).await?;
+        let files = load_attachments(db, note_ids).await?;
+
+        Ok(NoteStats {
+            votes: votes
+                .into_iter()
+                .map(|(note_id, votes, user_vote)| (note_id, (votes, user_vote)))
+                .collect(),
+            saves: saves
+                .into_iter()
+                .map(|(note_id, saves, user_saves)| (note_id, (saves, user_saves > 0)))
+                .collect(),
+            tags,
+            files,
+        })
+    }
+
+    fn response(&self, note: note::Model, short: bool) -> NoteResponse {
+        let (votes, user_vote) = self.votes.get(&note.id).copied().unwrap_or_default();
+        let (saves, user_bookmark) = self.saves.get(&note.id).copied().unwrap_or_default();
+        let tags = self.tags.get(&note.id).cloned().unwrap_or_default();
+        let files = self.files.get(&note.id).cloned().unwrap_or_default();
 
         let content: String = if short {
-            self.content.clone().chars().take(200).collect()
+            note.content.chars().take(200).collect()
         } else {
-            self.content.clone()
+            note.content
         };
 
-        Ok(NoteResponse {
-            id: self.id,
-            user_id: self.user_id,
-            created_at: self.created_at,
-            title: self.title.clone(),
+        NoteResponse {
+            id: note.id,
+            user_id: note.user_id,
+            created_at: note.created_at,
+            title: note.title,
             content,
-            public: self.public,
-            saves,
-            user_vote,
-            user_bookmark: is_bookmarked,
-            votes: votes,
-        })
+            public: note.public,
+            saves: saves as i32,
+            user_vote: user_vote as i32,
+            user_bookmark,
+            votes: votes as i32,
+            tags,
+            files,
+        }
+    }
+}
+
+/// Files attached to each note, oldest first, without their contents
+async fn load_attachments(
+    db: &DatabaseConnection,
+    note_ids: &[i32],
+) -> Result<HashMap<i32, Vec<AttachmentResponse>>> {
+    let rows: Vec<(i32, i32, String, DateTime<Utc>)> = note_files::Entity::find()
+        .select_only()
+        .column(note_files::Column::NoteId)
+        .columns([
+            file::Column::Id,
+            file::Column::Filename,
+            file::Column::CreatedAt,
+        ])
+        .inner_join(file::Entity)
+        .filter(note_files::Column::NoteId.is_in(note_ids.iter().copied()))
+        .order_by_asc(file::Column::Id)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut files: HashMap<i32, Vec<AttachmentResponse>> = HashMap::new();
+    for (note_id, id, filename, created_at) in rows {
+        files.entry(note_id).or_default().push(AttachmentResponse {
+            id,
+            filename,
+            created_at,
+        });
+    }
+
+    Ok(files)
+}
+
+impl note::Model {
+    pub async fn to_response(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+        short: bool,
+    ) -> Result<NoteResponse> {
+        let stats = NoteStats::load(db, &[self.id], user_id).await?;
+
+        Ok(stats.response(self.clone(), short))
     }
 }
 
 impl ManyNotesResponse {
-    pub async fn response_from_array(
-        notes: Vec<note::Model>,
+    pub async fn response_from_page(
+        page: Page<note::Model>,
         db: &DatabaseConnection,
-        user_id: i32, // Added user_id parameter
+        user_id: i32,
     ) -> Result<ManyNotesResponse> {
-        let mut responses = vec![];
-        for note in notes {
-            responses.push(note.to_response(db, user_id, false).await?);
-        }
-        Ok(ManyNotesResponse { notes: responses })
+        let note_ids: Vec<i32> = page.items.iter().map(|note| note.id).collect();
+        let stats = NoteStats::load(db, &note_ids, user_id).await?;
+
+        Ok(ManyNotesResponse {
+            notes: page
+                .items
+                .into_iter()
+                .map(|note| stats.response(note, false))
+                .collect(),
+            next_cursor: page.next_cursor,
+        })
     }
 }
 
+/// Loads one page of notes, newest first
+pub async fn paginate_notes(
+    db: &DatabaseConnection,
+    select: Select<note::Entity>,
+    page: &PageQuery,
+) -> AxumResult<Page<note::Model>> {
+    paginate(
+        db,
+        select,
+        (note::Column::CreatedAt, note::Column::Id),
+        page,
+        |note| (note.created_at, note.id),
+    )
+    .await
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct NoteCreateResponse {
     pub success: bool,
@@ -126,6 +315,10 @@ async fn create_note(
     Extension(user): Extension<user::Model>,
     Valid(Json(body)): Valid<Json<NoteCreateRequest>>,
 ) -> AxumResult<Json<NoteCreateResponse>> {
+    if body.public == Some(true) {
+        require_verified(&user, state.settings.auth.verify_before_publishing)?;
+    }
+
     let model = note::ActiveModel {
         user_id: Set(user.id),
         title: Set(body.title),
@@ -135,7 +328,10 @@ async fn create_note(
         ..Default::default()
     };
 
-    model.insert(&state.db).await?;
+    let txn = state.db.begin().await?;
+    let note = model.insert(&txn).await?;
+    record_revision(&txn, &note, user.id).await?;
+    txn.commit().await?;
 
     Ok(Json(NoteCreateResponse { success: true }))
 }
@@ -152,19 +348,36 @@ pub struct NoteResponse {
     pub user_bookmark: bool,
     pub user_vote: i32,
     pub votes: i32,
+    /// Tag names, alphabetically
+    pub tags: Vec<String>,
+    /// Attached files, like the pages an AI note was generated from
+    pub files: Vec<AttachmentResponse>,
+}
+
+/// A file attached to a note, its contents are at `/api/files/{id}`
+#[derive(Clone, Serialize, ToSchema)]
+pub struct AttachmentResponse {
+    pub id: i32,
+    pub filename: String,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Serialize, ToSchema)]
 pub struct ManyNotesResponse {
     pub notes: Vec<NoteResponse>,
+
+    /// Pass as `cursor` to get the next page, missing on the last page
+    pub next_cursor: Option<String>,
 }
 
 /// Get all your notes
 #[utoipa::path(
     method(get),
     path = "/",
+    params(TagFilter, PageQuery),
     responses(
         (status = OK, description = "Success", body = ManyNotesResponse),
+        (status = BAD_REQUEST, description = "Invalid tag name"),
         (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
     ),
     tag = "Notes"
@@ -172,22 +385,29 @@ pub struct ManyNotesResponse {
 async fn get_notes(
     Extension(state): Extension<AppState>,
     Extension(user): Extension<user::Model>,
+    Valid(Query(tags)): Valid<Query<TagFilter>>,
+    Valid(Query(page)): Valid<Query<PageQuery>>,
 ) -> AxumResult<Json<ManyNotesResponse>> {
-    let notes = note::Entity::find()
+    let mut select = note::Entity::find()
         .filter(note::Column::UserId.eq(user.id))
-        .order_by_desc(note::Column::CreatedAt)
-        .all(&state.db)
-        .await?;
+        .filter(note::Column::DeletedAt.is_null());
+    if let Some(tagged) = tags.condition()? {
+        select = select.filter(tagged);
+    }
+    let notes = paginate_notes(&state.db, select, &page).await?;
 
     Ok(Json(
-        ManyNotesResponse::response_from_array(notes, &state.db, user.id).await?,
+        ManyNotesResponse::response_from_page(notes, &state.db, user.id).await?,
     ))
 }
 
 /// Get all your bookmarked notes
+///
+/// Notes that were made private or hidden since you bookmarked them are left out.
 #[utoipa::path(
     method(get),
     path = "/bookmark",
+    params(PageQuery),
     responses(
         (status = OK, description = "Success", body = ManyNotesResponse),
         (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
@@ -197,20 +417,91 @@ async fn get_notes(
 async fn get_bookmarked_notes(
     Extension(state): Extension<AppState>,
     Extension(user): Extension<user::Model>,
+    Valid(Query(page)): Valid<Query<PageQuery>>,
 ) -> AxumResult<Json<ManyNotesResponse>> {
-    let saves = save::Entity::find()
+    let select = note::Entity::find()
+        .inner_join(save::Entity)
         .filter(save::Column::UserId.eq(user.id))
-        .all(&state.db)
-        .await?;
-
-    let note_ids: Vec<i32> = saves.into_iter().map(|s| s.note_id).collect();
-
-    let notes = note::Entity::find()
-        .filter(note::Column::Id.is_in(note_ids))
-        .all(&state.db)
-        .await?;
+        .filter(note::Column::DeletedAt.is_null())
+        .filter(
+            Condition::any().add(note::Column::UserId.eq(user.id)).add(
+                Condition::all()
+                    .add(note::Column::Public.eq(true))
+                    .add(note::Column::HiddenAt.is_null()),
+            ),
+        )
+        .filter(not_blocked_by(user.id));
+    let notes = paginate_notes(&state.db, select, &page).await?;
 
     Ok(Json(
-        ManyNotesResponse::response_from_array(notes, &state.db, user.id).await?,
+        ManyNotesResponse::response_from_page(notes, &state.db, user.id).await?,
     ))
 }
+
+#[derive(Serialize, ToSchema)]
+pub struct TrashedNoteResponse {
+    pub note: NoteResponse,
+    pub deleted_at: DateTime<Utc>,
+
+    /// When the note is deleted for good
+    pub purge_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ManyTrashedNotesResponse {
+    pub notes: Vec<TrashedNoteResponse>,
+
+    /// Pass as `cursor` to get the next page, missing on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Get your deleted notes
+///
+/// Most recently deleted first. Notes stay in the trash until `purge_at`.
+#[utoipa::path(
+    method(get),
+    path = "/trash",
+    params(PageQuery),
+    responses(
+        (status = OK, description = "Success", body = ManyTrashedNotesResponse),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn get_trashed_notes(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Valid(Query(page)): Valid<Query<PageQuery>>,
+) -> AxumResult<Json<ManyTrashedNotesResponse>> {
+    let select = note::Entity::find()
+        .filter(note::Column::UserId.eq(user.id))
+        .filter(note::Column::DeletedAt.is_not_null());
+    let notes = paginate(
+        &state.db,
+        select,
+        (note::Column::DeletedAt, note::Column::Id),
+        &page,
+        |note| (note.deleted_at.unwrap_or(note.created_at), note.id),
+    )
+    .await?;
+
+    let note_ids: Vec<i32> = notes.items.iter().map(|note| note.id).collect();
+    let stats = NoteStats::load(&state.db, &note_ids, user.id).await?;
+    let retention = TimeDelta::seconds(state.settings.notes.trash_retention);
+
+    Ok(Json(ManyTrashedNotesResponse {
+        notes: notes
+            .items
+            .into_iter()
+            .map(|note| {
+                let deleted_at = note.deleted_at.unwrap_or(note.created_at);
+                TrashedNoteResponse {
+                    note: stats.response(note, true),
+                    deleted_at,
+                    purge_at: deleted_at + retention,
+                }
+            })
+            .collect(),
+        next_cursor: notes.next_cursor,
+    }))
+}
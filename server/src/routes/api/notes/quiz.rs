@@ -2,8 +2,7 @@ use async_openai::types::{
     ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
     ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs,
 };
-use axum::{Extension, Json, extract::Path};
-use axum_valid::Valid;
+use axum::{Extension, Json, extract::Path, middleware};
 use color_eyre::eyre::eyre;
 use sea_orm::{
     ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, ModelTrait, QueryFilter,
@@ -11,19 +10,33 @@ use sea_orm::{
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use utoipa_axum::{router::OpenApiRouter, routes};
-use validator::Validate;
 
 use crate::{
-    entity::{note, question, quiz, user},
+    entity::{question, quiz, token::Scope, user},
     errors::{AxumError, AxumResult, NotFoundError},
-    middlewares::UnauthorizedError,
+    middlewares::{RateLimitGroup, ScopeRule, UnauthorizedError, rate_limit, require_scope},
+    policy::{Action, authorize},
+    routes::api::notes::{find_note, find_readable_note},
     state::AppState,
+    util::verification::require_verified,
 };
 
 pub fn routes() -> OpenApiRouter<AppState> {
+    // Only generating a quiz costs AI budget, reading one is counted like any other request
+    let (schemas, paths, create_quiz) = routes!(create_quiz);
+    let create_quiz = create_quiz
+        .layer(middleware::from_fn_with_state(
+            RateLimitGroup::Ai,
+            rate_limit,
+        ))
+        .layer(middleware::from_fn_with_state(
+            ScopeRule::all(Scope::AiGenerate),
+            require_scope,
+        ));
+
     OpenApiRouter::new()
         .routes(routes!(get_quizes))
-        .routes(routes!(create_quiz))
+        .routes((schemas, paths, create_quiz))
 }
 
 #[derive(Serialize, ToSchema, Deserialize)]
@@ -58,14 +71,7 @@ pub async fn get_quizes(
     Extension(user): Extension<user::Model>,
     Path(id): Path<i32>,
 ) -> AxumResult<Json<QuizResponse>> {
-    let note = note::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))?;
-
-    if note.user_id != user.id && !note.public {
-        return Err(AxumError::not_found(eyre!("Quiz not found for this note")));
-    }
+    find_readable_note(&state, &user, id).await?;
 
     let quiz_model = quiz::Entity::find()
         .filter(quiz::Column::NoteId.eq(id))
@@ -118,16 +124,11 @@ pub async fn create_quiz(
     Extension(user): Extension<user::Model>,
     Path(id): Path<i32>,
 ) -> AxumResult<Json<QuizResponse>> {
-    let note = note::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or_else(|| AxumError::not_found(eyre!("Note not found")))?;
+    let note = find_note(&state.db, id).await?;
 
-    if note.user_id != user.id {
-        return Err(AxumError::forbidden(eyre!(
-            "You don't have permission to create quiz for this note"
-        )));
-    }
+    authorize(&user, &note, Action::Edit)?;
+
+    require_verified(&user, state.settings.auth.verify_before_ai)?;
 
     let existing_quiz = quiz::Entity::find()
         .filter(quiz::Column::NoteId.eq(id))
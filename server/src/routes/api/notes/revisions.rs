@@ -0,0 +1,321 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, Query},
+};
+use axum_valid::Valid;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::eyre;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    entity::{note, note_revision, user},
+    errors::{AxumError, AxumResult, NotFoundError},
+    middlewares::UnauthorizedError,
+    policy::{Action, authorize},
+    routes::api::notes::{NoteResponse, find_note},
+    state::AppState,
+    util::{
+        pagination::{PageQuery, paginate},
+        revisions::record_revision,
+        verification::require_verified,
+    },
+};
+
+/// A note's edit history, under `/api/notes/{id}/revisions`. Only the owner can see it.
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_revisions))
+        .routes(routes!(get_revision_diff))
+        .routes(routes!(get_revision))
+        .routes(routes!(restore_revision))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RevisionSummaryResponse {
+    pub id: i32,
+    pub author_id: i32,
+    pub created_at: DateTime<Utc>,
+    pub title: String,
+    pub public: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ManyRevisionsResponse {
+    pub revisions: Vec<RevisionSummaryResponse>,
+
+    /// Pass as `cursor` to get the next page, missing on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RevisionResponse {
+    pub id: i32,
+    pub note_id: i32,
+    pub author_id: i32,
+    pub created_at: DateTime<Utc>,
+    pub title: String,
+    pub content: String,
+    pub public: bool,
+}
+
+impl From<note_revision::Model> for RevisionResponse {
+    fn from(revision: note_revision::Model) -> Self {
+        Self {
+            id: revision.id,
+            note_id: revision.note_id,
+            author_id: revision.author_id,
+            created_at: revision.created_at,
+            title: revision.title,
+            content: revision.content,
+            public: revision.public,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DiffQuery {
+    /// Older revision ID
+    pub from: i32,
+    /// Newer revision ID
+    pub to: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffLineKind {
+    Unchanged,
+    Added,
+    Removed,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+
+    /// Line number in `from`, starting at 1, missing for added lines
+    pub old_line: Option<usize>,
+
+    /// Line number in `to`, starting at 1, missing for removed lines
+    pub new_line: Option<usize>,
+
+    pub text: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RevisionDiffResponse {
+    pub from: RevisionSummaryResponse,
+    pub to: RevisionSummaryResponse,
+
+    /// Every line of both contents, in order
+    pub lines: Vec<DiffLine>,
+}
+
+/// Get a note's revisions
+///
+/// Newest first. Every change to the title, content or visibility makes a new revision. Notes
+/// that weren't changed since revisions started being kept have none yet.
+#[utoipa::path(
+    method(get),
+    path = "/",
+    params(
+        ("id" = i32, Path, description = "Note ID"),
+        PageQuery
+    ),
+    responses(
+        (status = OK, description = "Success", body = ManyRevisionsResponse),
+        (status = FORBIDDEN, description = "Not your note"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn get_revisions(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+    Valid(Query(page)): Valid<Query<PageQuery>>,
+) -> AxumResult<Json<ManyRevisionsResponse>> {
+    let note = find_note(&state.db, id).await?;
+    authorize(&user, &note, Action::Edit)?;
+
+    let select = note_revision::Entity::find().filter(note_revision::Column::NoteId.eq(note.id));
+    let revisions = paginate(
+        &state.db,
+        select,
+        (note_revision::Column::CreatedAt, note_revision::Column::Id),
+        &page,
+        |revision| (revision.created_at, revision.id),
+    )
+    .await?;
+
+    Ok(Json(ManyRevisionsResponse {
+        revisions: revisions.items.iter().map(summary).collect(),
+        next_cursor: revisions.next_cursor,
+    }))
+}
+
+/// Get a single revision
+#[utoipa::path(
+    method(get),
+    path = "/{revision_id}",
+    params(
+        ("id" = i32, Path, description = "Note ID"),
+        ("revision_id" = i32, Path, description = "Revision ID")
+    ),
+    responses(
+        (status = OK, description = "Success", body = RevisionResponse),
+        (status = FORBIDDEN, description = "Not your note"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn get_revision(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path((id, revision_id)): Path<(i32, i32)>,
+) -> AxumResult<Json<RevisionResponse>> {
+    let note = find_note(&state.db, id).await?;
+    authorize(&user, &note, Action::Edit)?;
+
+    let revision = find_revision(&state, note.id, revision_id).await?;
+
+    Ok(Json(revision.into()))
+}
+
+/// Compare two revisions
+///
+/// Diffs the content line by line.
+#[utoipa::path(
+    method(get),
+    path = "/diff",
+    params(
+        ("id" = i32, Path, description = "Note ID"),
+        DiffQuery
+    ),
+    responses(
+        (status = OK, description = "Success", body = RevisionDiffResponse),
+        (status = FORBIDDEN, description = "Not your note"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn get_revision_diff(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+    Query(query): Query<DiffQuery>,
+) -> AxumResult<Json<RevisionDiffResponse>> {
+    let note = find_note(&state.db, id).await?;
+    authorize(&user, &note, Action::Edit)?;
+
+    let from = find_revision(&state, note.id, query.from).await?;
+    let to = find_revision(&state, note.id, query.to).await?;
+
+    // Otherwise an unchanged last line differs once another line is added after it
+    let terminated = |content: &str| {
+        if content.ends_with('\n') {
+            content.to_string()
+        } else {
+            format!("{content}\n")
+        }
+    };
+    let (old, new) = (terminated(&from.content), terminated(&to.content));
+
+    let lines = TextDiff::from_lines(&old, &new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            kind: match change.tag() {
+                ChangeTag::Equal => DiffLineKind::Unchanged,
+                ChangeTag::Insert => DiffLineKind::Added,
+                ChangeTag::Delete => DiffLineKind::Removed,
+            },
+            old_line: change.old_index().map(|index| index + 1),
+            new_line: change.new_index().map(|index| index + 1),
+            text: change.value().trim_end_matches(['\r', '\n']).to_string(),
+        })
+        .collect();
+
+    Ok(Json(RevisionDiffResponse {
+        from: summary(&from),
+        to: summary(&to),
+        lines,
+    }))
+}
+
+/// Restore a revision
+///
+/// Sets the note back to the revision's title, content and visibility. This is saved as a new
+/// revision, so nothing in between is lost.
+#[utoipa::path(
+    method(post),
+    path = "/{revision_id}/restore",
+    params(
+        ("id" = i32, Path, description = "Note ID"),
+        ("revision_id" = i32, Path, description = "Revision ID")
+    ),
+    responses(
+        (status = OK, description = "Success", body = NoteResponse),
+        (status = FORBIDDEN, description = "Not your note"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn restore_revision(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path((id, revision_id)): Path<(i32, i32)>,
+) -> AxumResult<Json<NoteResponse>> {
+    let note = find_note(&state.db, id).await?;
+    authorize(&user, &note, Action::Edit)?;
+
+    let revision = find_revision(&state, note.id, revision_id).await?;
+
+    if revision.public && !note.public {
+        require_verified(&user, state.settings.auth.verify_before_publishing)?;
+    }
+
+    // The revision being restored means the note's history was already started
+    let txn = state.db.begin().await?;
+    let mut note: note::ActiveModel = note.into();
+    note.title = Set(revision.title);
+    note.content = Set(revision.content);
+    note.public = Set(revision.public);
+    let note = note.update(&txn).await?;
+
+    record_revision(&txn, &note, user.id).await?;
+    txn.commit().await?;
+
+    Ok(Json(note.to_response(&state.db, user.id, false).await?))
+}
+
+async fn find_revision(
+    state: &AppState,
+    note_id: i32,
+    revision_id: i32,
+) -> AxumResult<note_revision::Model> {
+    note_revision::Entity::find_by_id(revision_id)
+        .filter(note_revision::Column::NoteId.eq(note_id))
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("Revision not found")))
+}
+
+fn summary(revision: &note_revision::Model) -> RevisionSummaryResponse {
+    RevisionSummaryResponse {
+        id: revision.id,
+        author_id: revision.author_id,
+        created_at: revision.created_at,
+        title: revision.title.clone(),
+        public: revision.public,
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use axum::{Extension, Json, extract::Query};
+use axum_valid::Valid;
+use sea_orm::{
+    ColumnTrait, Condition, EntityTrait, Order, QueryFilter, QueryOrder, QuerySelect, QueryTrait,
+    sea_query::{Alias, Expr, ExprTrait, Query as SeaQuery, SelectStatement},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
+
+use crate::{
+    entity::{file, note, note_files, save, user},
+    errors::AxumResult,
+    middlewares::UnauthorizedError,
+    routes::api::notes::{NoteResponse, NoteStats, discoverable_notes},
+    state::AppState,
+    util::{
+        blocks::{not_blocked_by, visible_authors},
+        search::{FILE_SEARCH_COLUMN, NOTE_SEARCH_COLUMN, TEXT_SEARCH_CONFIG, to_tsquery},
+    },
+};
+
+/// Results per page when the client doesn't ask for a number
+const DEFAULT_SEARCH_LIMIT: u64 = 20;
+
+/// How much a match in an attached file counts compared to one in the note itself
+const FILE_RANK_WEIGHT: f32 = 0.5;
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(search_notes))
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchScope {
+    /// Your own notes and every public note
+    #[default]
+    All,
+    /// Only your own notes
+    Own,
+    /// Only notes you bookmarked
+    Bookmarked,
+    /// Only public notes, including your own
+    Public,
+}
+
+#[derive(Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// What to look for. Supports `"quoted phrases"`, `or` and `-excluded` words.
+    #[validate(length(min = 1, max = 256))]
+    pub q: String,
+
+    #[serde(default)]
+    pub scope: SearchScope,
+
+    /// Results per page, 20 by default
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<u64>,
+
+    /// Results to skip, for the following pages
+    #[validate(range(max = 1000))]
+    pub offset: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchResult {
+    pub note: NoteResponse,
+
+    /// Relevance, higher is better
+    pub rank: f32,
+
+    /// The best matching parts of the content, HTML-escaped, with matches wrapped in `<mark>`
+    pub snippet: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchResponse {
+    pub results: Vec<SearchResult>,
+}
+
+/// Search notes
+///
+/// Matches the title and content of notes and the OCR text of files attached to them. Results
+/// are ranked by relevance. Private notes only ever match for their owner.
+#[utoipa::path(
+    method(get),
+    path = "/",
+    params(SearchQuery),
+    responses(
+        (status = OK, description = "Success", body = SearchResponse),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn search_notes(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Valid(Query(query)): Valid<Query<SearchQuery>>,
+) -> AxumResult<Json<SearchResponse>> {
+    let note_vector = || Expr::col((note::Entity, Alias::new(NOTE_SEARCH_COLUMN)));
+    let file_vector = || Expr::col((file::Entity, Alias::new(FILE_SEARCH_COLUMN)));
+    let tsquery = || to_tsquery(&query.q);
+
+    let file_match = attached_files()
+        .expr(Expr::val(1))
+        .and_where(Expr::cust_with_exprs(
+            "$1 @@ $2",
+            [file_vector(), tsquery()],
+        ))
+        .to_owned();
+    let file_rank = attached_files()
+        .expr(Expr::cust_with_exprs(
+            "max(ts_rank($1, $2))",
+            [file_vector(), tsquery()],
+        ))
+        .to_owned();
+
+    let rank = Expr::cust_with_exprs(
+        format!("(ts_rank($1, $2) + coalesce($3, 0) * {FILE_RANK_WEIGHT})::real"),
+        [note_vector(), tsquery(), file_rank.into()],
+    );
+    // ts_headline doesn't escape the text it's given
+    let snippet = Expr::cust_with_exprs(
+        format!(
+            "ts_headline('{TEXT_SEARCH_CONFIG}', \
+            replace(replace(replace($1, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), $2, \
+            'StartSel=<mark>, StopSel=</mark>, MaxWords=35, MinWords=15, MaxFragments=2')"
+        ),
+        [Expr::col((note::Entity, note::Column::Content)), tsquery()],
+    );
+
+    let matches = Condition::any()
+        .add(Expr::cust_with_exprs(
+            "$1 @@ $2",
+            [note_vector(), tsquery()],
+        ))
+        .add(Expr::exists(file_match));
+
+    let rows: Vec<(i32, f32, String)> = note::Entity::find()
+        .select_only()
+        .column(note::Column::Id)
+        .column_as(rank, "rank")
+        .column_as(snippet, "snippet")
+        .filter(matches)
+        .filter(note::Column::DeletedAt.is_null())
+        .filter(scope_condition(query.scope, user.id))
+        .order_by(Expr::cust("rank"), Order::Desc)
+        .order_by(note::Column::Id, Order::Desc)
+        .limit(query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+        .offset(query.offset.unwrap_or(0))
+        .into_tuple()
+        .all(&state.db)
+        .await?;
+
+    let note_ids: Vec<i32> = rows.iter().map(|(id, _, _)| *id).collect();
+    let mut notes: HashMap<i32, note::Model> = note::Entity::find()
+        .filter(note::Column::Id.is_in(note_ids.clone()))
+        .all(&state.db)
+        .await?
+        .into_iter()
+        .map(|note| (note.id, note))
+        .collect();
+    let stats = NoteStats::load(&state.db, &note_ids, user.id).await?;
+
+    let results = rows
+        .into_iter()
+        .filter_map(|(id, rank, snippet)| {
+            let note = notes.remove(&id)?;
+            Some(SearchResult {
+                note: stats.response(note, true),
+                rank,
+                snippet,
+            })
+        })
+        .collect();
+
+    Ok(Json(SearchResponse { results }))
+}
+
+/// Files attached to the note in the outer query, without any selected columns
+fn attached_files() -> SelectStatement {
+    SeaQuery::select()
+        .from(note_files::Entity)
+        .inner_join(
+            file::Entity,
+            Expr::col((file::Entity, file::Column::Id))
+                .equals((note_files::Entity, note_files::Column::FileId)),
+        )
+        .and_where(
+            Expr::col((note_files::Entity, note_files::Column::NoteId))
+                .equals((note::Entity, note::Column::Id)),
+        )
+        .to_owned()
+}
+
+/// Which notes `user_id` may find in `scope`. Unlike reading a note by ID, moderators don't get
+/// to search other people's private or hidden notes.
+fn scope_condition(scope: SearchScope, user_id: i32) -> Condition {
+    let own = || Condition::all().add(note::Column::UserId.eq(user_id));
+    let public = || {
+        Condition::all()
+            .add(note::Column::Public.eq(true))
+            .add(note::Column::HiddenAt.is_null())
+    };
+
+    match scope {
+        SearchScope::All => discoverable_notes(user_id),
+        SearchScope::Own => own(),
+        SearchScope::Public => public().add(visible_authors(user_id)),
+        SearchScope::Bookmarked => {
+            let bookmarked = save::Entity::find()
+                .select_only()
+                .column(save::Column::NoteId)
+                .filter(save::Column::UserId.eq(user_id))
+                .into_query();
+
+            Condition::all()
+                .add(note::Column::Id.in_subquery(bookmarked))
+                .add(
+                    Condition::any()
+                        .add(own())
+                        .add(public().add(not_blocked_by(user_id))),
+                )
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use axum::{Extension, Json, extract::Path};
+use axum_valid::Valid;
+use sea_orm::TransactionTrait;
+use serde::Deserialize;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
+
+use crate::{
+    entity::user,
+    errors::{AxumResult, NotFoundError},
+    middlewares::UnauthorizedError,
+    policy::{Action, authorize},
+    routes::api::notes::{NoteResponse, find_note},
+    state::AppState,
+    util::tags::{MAX_TAGS_PER_NOTE, normalize_tags, set_note_tags},
+};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(set_tags))
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct SetTagsRequest {
+    /// Tag names, created when they don't exist yet. Names are lowercased and spaces become
+    /// dashes.
+    #[validate(length(max = MAX_TAGS_PER_NOTE))]
+    pub tags: Vec<String>,
+}
+
+/// Set a note's tags
+///
+/// Replaces all of the note's tags. An empty list removes them.
+#[utoipa::path(
+    method(put),
+    path = "/tags",
+    params(
+        ("id" = i32, Path, description = "Note ID")
+    ),
+    request_body = SetTagsRequest,
+    responses(
+        (status = OK, description = "Success", body = NoteResponse),
+        (status = BAD_REQUEST, description = "Invalid tag name"),
+        (status = FORBIDDEN, description = "Not your note"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Notes"
+)]
+async fn set_tags(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+    Valid(Json(body)): Valid<Json<SetTagsRequest>>,
+) -> AxumResult<Json<NoteResponse>> {
+    let note = find_note(&state.db, id).await?;
+
+    authorize(&user, &note, Action::Edit)?;
+
+    let names = normalize_tags(body.tags.iter().map(String::as_str))?;
+
+    let txn = state.db.begin().await?;
+    set_note_tags(&txn, note.id, &names).await?;
+    txn.commit().await?;
+
+    Ok(Json(note.to_response(&state.db, user.id, false).await?))
+}
@@ -0,0 +1,486 @@
+use axum::{Extension, Json, extract::Path};
+use axum_valid::Valid;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::Utc;
+use color_eyre::eyre::{Context, eyre};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header, jwk::JwkSet};
+use redis::AsyncCommands;
+use reqwest::Url;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
+
+use super::login::{LoginResult, start_two_factor_challenge};
+use crate::{
+    entity::{oidc_identity, user},
+    errors::{AxumError, AxumResult, NotFoundError},
+    settings::OidcProvider,
+    state::AppState,
+    util::{
+        passwords::hash_password,
+        sessions::start_session,
+        tokens::generate_token,
+        usernames::{MAX_USERNAME_LENGTH, is_username_available, validate_username},
+    },
+};
+
+/// How long a started login may take before the user has to start over, in seconds
+const LOGIN_STATE_LIFETIME: u64 = 10 * 60;
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_providers))
+        .routes(routes!(authorize))
+        .routes(routes!(callback))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ProviderResponse {
+    pub id: String,
+    pub display_name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ManyProvidersResponse {
+    pub providers: Vec<ProviderResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuthorizeResponse {
+    /// Where to send the user to log in at the provider
+    pub authorization_url: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct CallbackRequest {
+    /// `code` query parameter the provider redirected back with
+    #[validate(length(min = 1, max = 2048))]
+    pub code: String,
+
+    /// `state` query parameter the provider redirected back with
+    #[validate(length(min = 1, max = 128))]
+    pub state: String,
+}
+
+/// What we remember between sending the user to the provider and them coming back
+#[derive(Deserialize, Serialize)]
+struct LoginState {
+    provider: String,
+    code_verifier: String,
+
+    /// Sent to the provider and expected back in the ID token, so a token issued for another
+    /// login can't be replayed into this one
+    nonce: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UserInfo {
+    sub: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    preferred_username: Option<String>,
+}
+
+/// List login providers
+#[utoipa::path(
+    method(get),
+    path = "/",
+    responses(
+        (status = OK, description = "Success", body = ManyProvidersResponse)
+    ),
+    tag = "Auth"
+)]
+async fn get_providers(Extension(state): Extension<AppState>) -> Json<ManyProvidersResponse> {
+    let providers = state
+        .settings
+        .oidc
+        .iter()
+        .map(|(id, provider)| ProviderResponse {
+            id: id.clone(),
+            display_name: provider.display_name.clone(),
+        })
+        .collect();
+
+    Json(ManyProvidersResponse { providers })
+}
+
+/// Start logging in with a provider
+///
+/// Returns the provider URL to send the user to. The provider then redirects back to the
+/// configured redirect URI, which should pass `code` and `state` on to the callback endpoint.
+#[utoipa::path(
+    method(get),
+    path = "/{provider}/authorize",
+    params(
+        ("provider" = String, Path, description = "Provider ID")
+    ),
+    responses(
+        (status = OK, description = "Success", body = AuthorizeResponse),
+        (status = NOT_FOUND, description = "Unknown provider", body = NotFoundError)
+    ),
+    tag = "Auth"
+)]
+async fn authorize(
+    Extension(state): Extension<AppState>,
+    Path(provider_id): Path<String>,
+) -> AxumResult<Json<AuthorizeResponse>> {
+    let provider = find_provider(&state, &provider_id)?;
+
+    let login_state = generate_token();
+    let code_verifier = generate_token();
+    let nonce = generate_token();
+    let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(&code_verifier));
+
+    let mut url = Url::parse(&provider.authorization_endpoint)
+        .wrap_err("Invalid OIDC authorization endpoint")?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", &provider.redirect_uri)
+        .append_pair("scope", &provider.scopes.join(" "))
+        .append_pair("state", &login_state)
+        .append_pair("nonce", &nonce)
+        .append_pair("code_challenge", &code_challenge)
+        .append_pair("code_challenge_method", "S256");
+
+    let value = serde_json::to_string(&LoginState {
+        provider: provider_id,
+        code_verifier,
+        nonce,
+    })?;
+
+    let mut redis = state.redis.clone();
+    redis
+        .set_ex::<_, _, ()>(state_key(&login_state), value, LOGIN_STATE_LIFETIME)
+        .await?;
+
+    Ok(Json(AuthorizeResponse {
+        authorization_url: url.to_string(),
+    }))
+}
+
+/// Finish logging in with a provider
+///
+/// Creates an account on first login, or links the provider to an existing account with the same
+/// email if both the provider and the account have it verified. Accounts with two-factor
+/// authentication get a challenge token instead, to be completed at `/api/login/2fa`.
+#[utoipa::path(
+    method(post),
+    path = "/{provider}/callback",
+    params(
+        ("provider" = String, Path, description = "Provider ID")
+    ),
+    responses(
+        (status = OK, description = "Success", body = LoginResult),
+        (status = BAD_REQUEST, description = "Invalid or expired login attempt"),
+        (status = CONFLICT, description = "An account with this email already exists"),
+        (status = UNPROCESSABLE_ENTITY, description = "The provider didn't share an email address"),
+        (status = NOT_FOUND, description = "Unknown provider", body = NotFoundError)
+    ),
+    tag = "Auth"
+)]
+async fn callback(
+    Extension(state): Extension<AppState>,
+    Path(provider_id): Path<String>,
+    Valid(Json(body)): Valid<Json<CallbackRequest>>,
+) -> AxumResult<Json<LoginResult>> {
+    let provider = find_provider(&state, &provider_id)?;
+
+    let mut redis = state.redis.clone();
+    let login_state: Option<String> = redis.get_del(state_key(&body.state)).await?;
+    let login_state = login_state
+        .map(|value| serde_json::from_str::<LoginState>(&value))
+        .transpose()?
+        .filter(|login_state| login_state.provider == provider_id)
+        .ok_or_else(|| AxumError::bad_request(eyre!("Invalid or expired login attempt")))?;
+
+    let user_info = fetch_user_info(provider, &login_state, &body.code).await?;
+
+    let txn = state.db.begin().await?;
+    let user = find_or_create_user(&txn, provider, &provider_id, user_info).await?;
+    txn.commit().await?;
+
+    // The provider only stands in for the password
+    if user.totp_enabled_at.is_some() {
+        let challenge = start_two_factor_challenge(&state, &user).await?;
+        return Ok(Json(LoginResult::TwoFactorRequired(challenge)));
+    }
+
+    let pair = start_session(&state, user.id).await?;
+
+    Ok(Json(LoginResult::Session(pair.into())))
+}
+
+fn find_provider<'a>(state: &'a AppState, id: &str) -> AxumResult<&'a OidcProvider> {
+    state
+        .settings
+        .oidc
+        .get(id)
+        .ok_or_else(|| AxumError::not_found(eyre!("Login provider not found")))
+}
+
+fn state_key(login_state: &str) -> String {
+    format!("oidc:state:{login_state}")
+}
+
+/// Exchanges the authorization code, checks the ID token that comes with it and asks the provider
+/// who it belongs to
+async fn fetch_user_info(
+    provider: &OidcProvider,
+    login_state: &LoginState,
+    code: &str,
+) -> AxumResult<UserInfo> {
+    let client = reqwest::Client::new();
+
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &provider.redirect_uri),
+        ("client_id", &provider.client_id),
+        ("code_verifier", &login_state.code_verifier),
+    ];
+    if let Some(secret) = &provider.client_secret {
+        form.push(("client_secret", secret));
+    }
+
+    let response = client
+        .post(&provider.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .wrap_err("Failed to reach OIDC token endpoint")?;
+
+    // The code is bad or was already used, which is the client's fault
+    if response.status().is_client_error() {
+        return Err(AxumError::bad_request(eyre!(
+            "Invalid or expired login attempt"
+        )));
+    }
+
+    let tokens: TokenResponse = response
+        .error_for_status()
+        .wrap_err("OIDC token endpoint returned an error")?
+        .json()
+        .await
+        .wrap_err("Invalid OIDC token response")?;
+
+    let claims = verify_id_token(&client, provider, &tokens.id_token, &login_state.nonce).await?;
+
+    let user_info: UserInfo = client
+        .get(&provider.userinfo_endpoint)
+        .bearer_auth(tokens.access_token)
+        .send()
+        .await
+        .wrap_err("Failed to reach OIDC userinfo endpoint")?
+        .error_for_status()
+        .wrap_err("OIDC userinfo endpoint returned an error")?
+        .json()
+        .await
+        .wrap_err("Invalid OIDC userinfo response")?;
+
+    // The userinfo response isn't signed, it only counts if it's about the same person
+    if user_info.sub != claims.sub {
+        warn!(
+            provider = provider.display_name,
+            "OIDC userinfo subject doesn't match the ID token"
+        );
+        return Err(AxumError::bad_request(eyre!(
+            "Invalid or expired login attempt"
+        )));
+    }
+
+    Ok(user_info)
+}
+
+/// Checks the ID token's signature against the provider's published keys, and that it was issued
+/// by the provider, for us, for this login attempt and hasn't expired
+async fn verify_id_token(
+    client: &reqwest::Client,
+    provider: &OidcProvider,
+    id_token: &str,
+    nonce: &str,
+) -> AxumResult<IdTokenClaims> {
+    let invalid = |reason: &str| {
+        warn!(
+            provider = provider.display_name,
+            reason, "Rejected OIDC ID token"
+        );
+        AxumError::bad_request(eyre!("Invalid or expired login attempt"))
+    };
+
+    let header = decode_header(id_token).map_err(|_| invalid("malformed"))?;
+
+    // Symmetric algorithms would be verified with a key anyone can read from the JWKS
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return Err(invalid("symmetric algorithm"));
+    }
+
+    let jwks: JwkSet = client
+        .get(&provider.jwks_uri)
+        .send()
+        .await
+        .wrap_err("Failed to reach OIDC JWKS endpoint")?
+        .error_for_status()
+        .wrap_err("OIDC JWKS endpoint returned an error")?
+        .json()
+        .await
+        .wrap_err("Invalid OIDC JWKS response")?;
+
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+    .ok_or_else(|| invalid("unknown key"))?;
+    let key = DecodingKey::from_jwk(jwk).map_err(|_| invalid("unusable key"))?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&provider.issuer]);
+    validation.set_audience(&[&provider.client_id]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+    let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+        .map_err(|_| invalid("signature or claims"))?
+        .claims;
+
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err(invalid("nonce"));
+    }
+
+    Ok(claims)
+}
+
+async fn find_or_create_user(
+    txn: &DatabaseTransaction,
+    provider: &OidcProvider,
+    provider_id: &str,
+    user_info: UserInfo,
+) -> AxumResult<user::Model> {
+    let identity = oidc_identity::Entity::find()
+        .filter(oidc_identity::Column::Provider.eq(provider_id))
+        .filter(oidc_identity::Column::Subject.eq(&user_info.sub))
+        .find_also_related(user::Entity)
+        .one(txn)
+        .await?;
+
+    if let Some((_, Some(user))) = identity {
+        return Ok(user);
+    }
+
+    let Some(email) = user_info.email.clone() else {
+        return Err(AxumError::unprocessable_entity(eyre!(
+            "The login provider didn't share an email address"
+        )));
+    };
+
+    let existing = user::Entity::find_by_email(email.clone()).one(txn).await?;
+
+    let user = match existing {
+        // Both sides must have proven the address, otherwise whoever registered it first could
+        // take over the other person's account
+        Some(user)
+            if provider.link_by_email
+                && user_info.email_verified
+                && user.email_verified_at.is_some() =>
+        {
+            user
+        }
+        Some(_) => {
+            return Err(AxumError::conflict(eyre!(
+                "An account with this email already exists, log in with your password instead"
+            ))
+            .with_code("account_exists"));
+        }
+        None => {
+            let now = Utc::now();
+            let username = available_username(txn, &user_info).await?;
+
+            // Nobody knows this password, it can be replaced through a password reset
+            let password_hash = hash_password(&generate_token())?;
+
+            user::ActiveModel {
+                username: Set(username),
+                email: Set(email),
+                password: Set(password_hash),
+                created_at: Set(now.naive_utc()),
+                email_verified_at: Set(user_info.email_verified.then_some(now.naive_utc())),
+                ..Default::default()
+            }
+            .insert(txn)
+            .await?
+        }
+    };
+
+    oidc_identity::ActiveModel {
+        user_id: Set(user.id),
+        provider: Set(provider_id.to_string()),
+        subject: Set(user_info.sub),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(txn)
+    .await?;
+
+    Ok(user)
+}
+
+/// Picks a username from the provider's suggestion, adding a number if it's taken or reserved
+async fn available_username(txn: &DatabaseTransaction, user_info: &UserInfo) -> AxumResult<String> {
+    let suggestion = user_info
+        .preferred_username
+        .as_deref()
+        .or_else(|| user_info.email.as_deref()?.split('@').next())
+        .unwrap_or_default();
+
+    // Leaves room for the longest suffix below
+    let mut base: String = suggestion
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .take(MAX_USERNAME_LENGTH - 8)
+        .collect();
+    if base.is_empty() {
+        base = "user".to_string();
+    }
+
+    let candidates = (0..10)
+        .map(|attempt| match attempt {
+            0 => base.clone(),
+            _ => format!("{base}{}", rand::random_range(1000..10000)),
+        })
+        .chain([format!("{base}{}", generate_token()[..8].to_lowercase())]);
+
+    for candidate in candidates {
+        if validate_username(&candidate).is_ok()
+            && is_username_available(txn, &candidate, None).await?
+        {
+            return Ok(candidate);
+        }
+    }
+
+    Err(AxumError::conflict(eyre!(
+        "Couldn't find a free username, try again"
+    )))
+}
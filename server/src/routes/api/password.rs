@@ -0,0 +1,167 @@
+use axum::{Extension, Json};
+use axum_valid::Valid;
+use chrono::{TimeDelta, Utc};
+use color_eyre::eyre::eyre;
+use http::StatusCode;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, TransactionTrait,
+};
+use serde::Deserialize;
+use tracing::warn;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
+
+use crate::{
+    entity::{password_reset, token, user},
+    errors::{AxumError, AxumResult},
+    mailer::Email,
+    state::AppState,
+    util::{
+        passwords::hash_password,
+        tokens::{generate_token, hash_token},
+    },
+};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(forgot_password))
+        .routes(routes!(reset_password))
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct ForgotPasswordRequest {
+    #[validate(email, length(max = 128))]
+    pub email: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct ResetPasswordRequest {
+    #[validate(length(min = 1, max = 128))]
+    pub token: String,
+
+    #[validate(length(min = 1, max = 128))]
+    pub new_password: String,
+}
+
+/// Request a password reset link
+///
+/// Always succeeds, so the response doesn't reveal whether an account uses the email
+#[utoipa::path(
+    method(post),
+    path = "/forgot",
+    responses(
+        (status = NO_CONTENT, description = "Reset link sent if the account exists")
+    ),
+    tag = "Auth"
+)]
+async fn forgot_password(
+    Extension(state): Extension<AppState>,
+    Valid(Json(body)): Valid<Json<ForgotPasswordRequest>>,
+) -> AxumResult<StatusCode> {
+    let Some(user) = user::Entity::find_by_email(body.email)
+        .one(&state.db)
+        .await?
+    else {
+        return Ok(StatusCode::NO_CONTENT);
+    };
+
+    let token = generate_token();
+    let now = Utc::now();
+    let lifetime = TimeDelta::seconds(state.settings.auth.password_reset_lifetime);
+
+    let reset = password_reset::ActiveModel {
+        user_id: Set(user.id),
+        token_hash: Set(hash_token(&token)),
+        created_at: Set(now),
+        expires_at: Set(now + lifetime),
+        ..Default::default()
+    };
+
+    reset.insert(&state.db).await?;
+
+    let email = Email {
+        to: user.email,
+        subject: "Reset your Mathisi password".to_string(),
+        body: format!(
+            "Hi {},\n\nUse this link to choose a new password:\n{}reset-password?token={}\n\nThe link expires in {} minutes. If you didn't ask for it, you can ignore this email.\n",
+            user.username,
+            state.settings.general.public_url,
+            token,
+            lifetime.num_minutes()
+        ),
+    };
+
+    // Sending in the background keeps the response time, as well as delivery problems, from
+    // revealing that the account exists
+    let mailer = state.mailer.clone();
+    tokio::spawn(async move {
+        if let Err(error) = mailer.send(email).await {
+            warn!(error = ?error, "Failed to send password reset email");
+        }
+    });
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Reset password
+///
+/// Consumes a reset token and signs out every session
+#[utoipa::path(
+    method(post),
+    path = "/reset",
+    responses(
+        (status = NO_CONTENT, description = "Password changed"),
+        (status = BAD_REQUEST, description = "Invalid or expired reset token")
+    ),
+    tag = "Auth"
+)]
+async fn reset_password(
+    Extension(state): Extension<AppState>,
+    Valid(Json(body)): Valid<Json<ResetPasswordRequest>>,
+) -> AxumResult<StatusCode> {
+    let invalid = || AxumError::bad_request(eyre!("Invalid or expired reset token"));
+    let token_hash = hash_token(&body.token);
+
+    let password_hash = hash_password(&body.new_password)?;
+
+    let txn = state.db.begin().await?;
+
+    let reset = password_reset::Entity::find_by_token_hash(token_hash.clone())
+        .one(&txn)
+        .await?
+        .ok_or_else(invalid)?;
+
+    // Claiming the token is what makes it single use. A concurrent request waits for this row and
+    // then finds it gone.
+    let claimed = password_reset::Entity::delete_many()
+        .filter(password_reset::Column::TokenHash.eq(token_hash))
+        .filter(password_reset::Column::ExpiresAt.gt(Utc::now()))
+        .exec(&txn)
+        .await?;
+    if claimed.rows_affected != 1 {
+        return Err(invalid());
+    }
+
+    // Every outstanding reset token for the user is spent, not only this one
+    password_reset::Entity::delete_many()
+        .filter(password_reset::Column::UserId.eq(reset.user_id))
+        .exec(&txn)
+        .await?;
+
+    let user = user::ActiveModel {
+        id: Set(reset.user_id),
+        password: Set(password_hash),
+        ..Default::default()
+    };
+    user.update(&txn).await?;
+
+    token::Entity::delete_many()
+        .filter(token::Column::UserId.eq(reset.user_id))
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
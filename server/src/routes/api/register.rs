@@ -1,25 +1,37 @@
-use argon2::Argon2;
-use chrono::{Utc};
-use password_hash::{PasswordHasher, SaltString, rand_core::OsRng};
+use chrono::Utc;
 
 use axum::{Extension, Json};
 use axum_valid::Valid;
 use color_eyre::eyre::eyre;
-use sea_orm::{ActiveModelTrait, ActiveValue::{Set}};
+use http::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ModelTrait};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 use utoipa::ToSchema;
 use utoipa_axum::{router::OpenApiRouter, routes};
 use validator::Validate;
 
-use crate::{entity::user, errors::AxumResult, state::AppState};
+use crate::{
+    entity::{email_verification, user},
+    errors::{AxumError, AxumResult, ConstraintError},
+    state::AppState,
+    util::{
+        passwords::hash_password,
+        tokens::hash_token,
+        usernames::{is_username_available, validate_username},
+        verification::send_verification_email,
+    },
+};
 
 pub fn routes() -> OpenApiRouter<AppState> {
-    OpenApiRouter::new().routes(routes!(login))
+    OpenApiRouter::new()
+        .routes(routes!(login))
+        .routes(routes!(verify_email))
 }
 
 #[derive(Deserialize, ToSchema, Validate)]
 pub struct RegisterRequest {
-    #[validate(length(min = 1, max = 128))]
+    #[validate(custom(function = "validate_username"))]
     pub username: String,
 
     #[validate(email, length(max = 128))]
@@ -34,12 +46,19 @@ pub struct RegisterResponse {
     pub success: bool,
 }
 
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct VerifyEmailRequest {
+    #[validate(length(min = 1, max = 128))]
+    pub token: String,
+}
+
 /// Register
 #[utoipa::path(
     method(post),
     path = "/",
     responses(
-        (status = OK, description = "Success", body = RegisterResponse)
+        (status = OK, description = "Success", body = RegisterResponse),
+        (status = CONFLICT, description = "Username or email already taken", body = ConstraintError)
     ),
     tag = "Auth"
 )]
@@ -47,23 +66,66 @@ async fn login(
     Extension(state): Extension<AppState>,
     Valid(Json(body)): Valid<Json<RegisterRequest>>,
 ) -> AxumResult<Json<RegisterResponse>> {
-    let salt = SaltString::generate(&mut OsRng);
-
-    let argon2 = Argon2::default();
+    if !is_username_available(&state.db, &body.username, None).await? {
+        return Err(AxumError::conflict(eyre!("username is already taken"))
+            .with_code("already_exists")
+            .with_field("username"));
+    }
 
-    let password_hash = argon2
-        .hash_password(body.password.as_bytes(), &salt)
-        .map_err(|e| eyre!("Failed to hash password: {}", e))?;
+    let password_hash = hash_password(&body.password)?;
 
     let model = user::ActiveModel {
         username: Set(body.username),
         email: Set(body.email),
-        password: Set(password_hash.to_string()),
-        created_at: Set(Utc::now().naive_utc()) ,
+        password: Set(password_hash),
+        created_at: Set(Utc::now().naive_utc()),
         ..Default::default()
     };
 
-    model.insert(&state.db).await?;
+    let user = model.insert(&state.db).await?;
+
+    // The account exists at this point, a failed email can be retried by resending
+    if let Err(error) = send_verification_email(&state, &user, &user.email).await {
+        warn!(error = ?error, "Failed to send verification email");
+    }
 
     Ok(Json(RegisterResponse { success: true }))
 }
+
+/// Verify email address
+#[utoipa::path(
+    method(post),
+    path = "/verify",
+    responses(
+        (status = NO_CONTENT, description = "Email verified"),
+        (status = BAD_REQUEST, description = "Invalid or expired verification token")
+    ),
+    tag = "Auth"
+)]
+async fn verify_email(
+    Extension(state): Extension<AppState>,
+    Valid(Json(body)): Valid<Json<VerifyEmailRequest>>,
+) -> AxumResult<StatusCode> {
+    let verification = email_verification::Entity::find_by_token_hash(hash_token(&body.token))
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::bad_request(eyre!("Invalid or expired verification token")))?;
+
+    if verification.expires_at <= Utc::now() {
+        return Err(AxumError::bad_request(eyre!(
+            "Invalid or expired verification token"
+        )));
+    }
+
+    let user = user::ActiveModel {
+        id: Set(verification.user_id),
+        email: Set(verification.email.clone()),
+        email_verified_at: Set(Some(Utc::now().naive_utc())),
+        ..Default::default()
+    };
+    user.update(&state.db).await?;
+
+    verification.delete(&state.db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,198 @@
+use axum::{Extension, Json, extract::Query, middleware};
+use axum_valid::Valid;
+use color_eyre::eyre::eyre;
+use sea_orm::{
+    ColumnTrait, EntityTrait, Order, QueryFilter, QueryOrder, QuerySelect, Select, sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
+
+use crate::{
+    entity::{note, note_tags, tag, token::Scope, user},
+    errors::{AxumError, AxumResult},
+    middlewares::{ScopeRule, UnauthorizedError, require_scope},
+    routes::api::notes::discoverable_notes,
+    state::AppState,
+    util::tags::{MAX_TAG_LENGTH, get_or_create_tags, normalize_tag},
+};
+
+/// Tags listed when the client doesn't ask for a number
+const DEFAULT_TAGS_LIMIT: u64 = 20;
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_popular_tags, create_tag))
+        .routes(routes!(autocomplete_tags))
+        .layer(middleware::from_fn_with_state(
+            ScopeRule::new(Scope::NotesRead, Scope::NotesWrite),
+            require_scope,
+        ))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TagResponse {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TagUsageResponse {
+    pub name: String,
+
+    /// How many notes you can see have this tag
+    pub notes: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ManyTagsResponse {
+    pub tags: Vec<TagUsageResponse>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateTagRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct PopularTagsQuery {
+    /// Tags to list, 20 by default
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<u64>,
+}
+
+#[derive(Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct AutocompleteQuery {
+    /// Start of the tag name
+    #[validate(length(min = 1, max = MAX_TAG_LENGTH))]
+    pub prefix: String,
+
+    /// Tags to list, 20 by default
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<u64>,
+}
+
+/// Get the most used tags
+///
+/// Counts your own notes and the public notes you can see.
+#[utoipa::path(
+    method(get),
+    path = "/",
+    params(PopularTagsQuery),
+    responses(
+        (status = OK, description = "Success", body = ManyTagsResponse),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Tags"
+)]
+async fn get_popular_tags(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Valid(Query(query)): Valid<Query<PopularTagsQuery>>,
+) -> AxumResult<Json<ManyTagsResponse>> {
+    let tags = tag_usage(user.id)
+        .limit(query.limit.unwrap_or(DEFAULT_TAGS_LIMIT))
+        .into_tuple()
+        .all(&state.db)
+        .await?;
+
+    Ok(Json(ManyTagsResponse {
+        tags: tags
+            .into_iter()
+            .map(|(name, notes)| TagUsageResponse { name, notes })
+            .collect(),
+    }))
+}
+
+/// Autocomplete tag names
+///
+/// Lists tags starting with `prefix`, most used first. Only tags on notes you can see are
+/// suggested.
+#[utoipa::path(
+    method(get),
+    path = "/autocomplete",
+    params(AutocompleteQuery),
+    responses(
+        (status = OK, description = "Success", body = ManyTagsResponse),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Tags"
+)]
+async fn autocomplete_tags(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Valid(Query(query)): Valid<Query<AutocompleteQuery>>,
+) -> AxumResult<Json<ManyTagsResponse>> {
+    // A lone trailing dash would be dropped by normalising, but it still narrows the prefix
+    let trailing_dash = query.prefix.ends_with([' ', '_', '-']);
+    let Some(mut prefix) = normalize_tag(&query.prefix) else {
+        return Ok(Json(ManyTagsResponse { tags: Vec::new() }));
+    };
+    if trailing_dash {
+        prefix.push('-');
+    }
+
+    // Normalised names have no `%` or `_`, so the prefix needs no escaping
+    let tags = tag_usage(user.id)
+        .filter(tag::Column::Name.starts_with(&prefix))
+        .limit(query.limit.unwrap_or(DEFAULT_TAGS_LIMIT))
+        .into_tuple()
+        .all(&state.db)
+        .await?;
+
+    Ok(Json(ManyTagsResponse {
+        tags: tags
+            .into_iter()
+            .map(|(name, notes)| TagUsageResponse { name, notes })
+            .collect(),
+    }))
+}
+
+/// Get or create a tag
+///
+/// Returns the existing tag when one with the same normalised name exists.
+#[utoipa::path(
+    method(post),
+    path = "/",
+    request_body = CreateTagRequest,
+    responses(
+        (status = OK, description = "Success", body = TagResponse),
+        (status = BAD_REQUEST, description = "Invalid tag name"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Tags"
+)]
+async fn create_tag(
+    Extension(state): Extension<AppState>,
+    Json(body): Json<CreateTagRequest>,
+) -> AxumResult<Json<TagResponse>> {
+    let name = normalize_tag(&body.name)
+        .ok_or_else(|| AxumError::bad_request(eyre!("Invalid tag: {}", body.name)))?;
+
+    let tag = get_or_create_tags(&state.db, &[name])
+        .await?
+        .pop()
+        .ok_or_else(|| AxumError::new(eyre!("Tag wasn't created")))?;
+
+    Ok(Json(TagResponse {
+        id: tag.id,
+        name: tag.name,
+    }))
+}
+
+/// Tag names with how many notes `user_id` can see have them, most used first
+fn tag_usage(user_id: i32) -> Select<note_tags::Entity> {
+    note_tags::Entity::find()
+        .select_only()
+        .column(tag::Column::Name)
+        .column_as(note_tags::Column::NoteId.count(), "notes")
+        .inner_join(tag::Entity)
+        .inner_join(note::Entity)
+        .filter(discoverable_notes(user_id))
+        .group_by(tag::Column::Id)
+        .order_by(Expr::cust("notes"), Order::Desc)
+        .order_by_asc(tag::Column::Name)
+}
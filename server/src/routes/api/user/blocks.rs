@@ -0,0 +1,266 @@
+use axum::{Extension, Json, extract::Path};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::eyre;
+use http::StatusCode;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    entity::{
+        block::{self, BlockKind},
+        follow, user,
+    },
+    errors::{AxumError, AxumResult, NotFoundError},
+    middlewares::UnauthorizedError,
+    routes::api::user::id::PublicUserResponse,
+    state::AppState,
+};
+
+/// Your block list, under `/api/user/blocks`
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(get_blocks))
+}
+
+/// Blocking and muting someone, under `/api/user/{id}`
+pub fn target_routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(block_user, unblock_user))
+        .routes(routes!(mute_user, unmute_user))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BlockResponse {
+    pub user: PublicUserResponse,
+    pub kind: BlockKind,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ManyBlocksResponse {
+    pub blocks: Vec<BlockResponse>,
+}
+
+/// List the users you blocked or muted
+#[utoipa::path(
+    method(get),
+    path = "/",
+    responses(
+        (status = OK, description = "Success", body = ManyBlocksResponse),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Users"
+)]
+async fn get_blocks(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+) -> AxumResult<Json<ManyBlocksResponse>> {
+    let blocks = block::Entity::find()
+        .filter(block::Column::UserId.eq(user.id))
+        .order_by_desc(block::Column::CreatedAt)
+        .all(&state.db)
+        .await?;
+
+    let targets = PublicUserResponse::response_from_ids(
+        blocks.iter().map(|block| block.target_id).collect(),
+        &state.db,
+        user.id,
+    )
+    .await?;
+
+    let blocks = blocks
+        .into_iter()
+        .zip(targets)
+        .map(|(block, target)| BlockResponse {
+            user: target,
+            kind: block.kind,
+            created_at: block.created_at,
+        })
+        .collect();
+
+    Ok(Json(ManyBlocksResponse { blocks }))
+}
+
+/// Block a user
+///
+/// Hides their notes from you and your notes from them, and stops them from voting on or
+/// bookmarking your notes. Either of you stops following the other.
+#[utoipa::path(
+    method(post),
+    path = "/block",
+    params(
+        ("id" = i32, Path, description = "User ID")
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Blocked"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNPROCESSABLE_ENTITY, description = "Can't block yourself"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Users"
+)]
+async fn block_user(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<StatusCode> {
+    restrict_user(&state, &user, id, BlockKind::Block).await?;
+
+    follow::Entity::delete_many()
+        .filter(
+            Condition::any()
+                .add(
+                    Condition::all()
+                        .add(follow::Column::FollowerId.eq(user.id))
+                        .add(follow::Column::FolloweeId.eq(id)),
+                )
+                .add(
+                    Condition::all()
+                        .add(follow::Column::FollowerId.eq(id))
+                        .add(follow::Column::FolloweeId.eq(user.id)),
+                ),
+        )
+        .exec(&state.db)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Unblock a user
+#[utoipa::path(
+    method(delete),
+    path = "/block",
+    params(
+        ("id" = i32, Path, description = "User ID")
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Not blocked anymore"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Users"
+)]
+async fn unblock_user(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<StatusCode> {
+    lift_restriction(&state, &user, id, BlockKind::Block).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Mute a user
+///
+/// Hides their notes from your feed and their profile. Replaces a block.
+#[utoipa::path(
+    method(post),
+    path = "/mute",
+    params(
+        ("id" = i32, Path, description = "User ID")
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Muted"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNPROCESSABLE_ENTITY, description = "Can't mute yourself"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Users"
+)]
+async fn mute_user(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<StatusCode> {
+    restrict_user(&state, &user, id, BlockKind::Mute).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Unmute a user
+#[utoipa::path(
+    method(delete),
+    path = "/mute",
+    params(
+        ("id" = i32, Path, description = "User ID")
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Not muted anymore"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Users"
+)]
+async fn unmute_user(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<StatusCode> {
+    lift_restriction(&state, &user, id, BlockKind::Mute).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Blocks or mutes `target_id`, replacing whichever of the two was there before
+async fn restrict_user(
+    state: &AppState,
+    user: &user::Model,
+    target_id: i32,
+    kind: BlockKind,
+) -> AxumResult<()> {
+    if target_id == user.id {
+        return Err(AxumError::unprocessable_entity(eyre!(
+            "You can't block or mute yourself"
+        )));
+    }
+
+    user::Entity::find_by_id(target_id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("User not found")))?;
+
+    let existing = block::Entity::find_by_user_target((user.id, target_id))
+        .one(&state.db)
+        .await?;
+
+    match existing {
+        Some(existing) if existing.kind == kind => {}
+        Some(existing) => {
+            let mut existing: block::ActiveModel = existing.into();
+            existing.kind = Set(kind);
+            existing.created_at = Set(Utc::now());
+            existing.update(&state.db).await?;
+        }
+        None => {
+            block::ActiveModel {
+                user_id: Set(user.id),
+                target_id: Set(target_id),
+                kind: Set(kind),
+                created_at: Set(Utc::now()),
+                ..Default::default()
+            }
+            .insert(&state.db)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn lift_restriction(
+    state: &AppState,
+    user: &user::Model,
+    target_id: i32,
+    kind: BlockKind,
+) -> AxumResult<()> {
+    block::Entity::delete_many()
+        .filter(block::Column::UserId.eq(user.id))
+        .filter(block::Column::TargetId.eq(target_id))
+        .filter(block::Column::Kind.eq(kind))
+        .exec(&state.db)
+        .await?;
+
+    Ok(())
+}
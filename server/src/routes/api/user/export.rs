@@ -0,0 +1,165 @@
+use axum::{Extension, Json, extract::Path};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::eyre;
+use http::StatusCode;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DerivePartialModel, EntityTrait, QueryFilter,
+    SqlErr,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    entity::{
+        export::{self, ExportStatus},
+        user,
+    },
+    errors::{AxumError, AxumResult, NotFoundError},
+    middlewares::UnauthorizedError,
+    state::AppState,
+    util::{
+        export::{download_url, run_export},
+        tokens::{generate_token, hash_token},
+    },
+};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(create_export))
+        .routes(routes!(get_export))
+}
+
+/// Everything about an export but the archive itself
+#[derive(DerivePartialModel)]
+#[sea_orm(entity = "export::Entity")]
+struct ExportInfo {
+    id: i32,
+    status: ExportStatus,
+    created_at: DateTime<Utc>,
+    finished_at: Option<DateTime<Utc>>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ExportResponse {
+    pub id: i32,
+    pub status: ExportStatus,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+
+    /// Only in the response that started the export and in the email sent once it's ready. Works
+    /// without authentication from then until `expires_at`.
+    pub download_url: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl From<ExportInfo> for ExportResponse {
+    fn from(export: ExportInfo) -> Self {
+        ExportResponse {
+            id: export.id,
+            status: export.status,
+            created_at: export.created_at,
+            finished_at: export.finished_at,
+            download_url: None,
+            expires_at: export.expires_at,
+        }
+    }
+}
+
+/// Export your data
+///
+/// Starts building a ZIP archive with your profile, notes, files, quizzes, bookmarks, votes,
+/// the people you follow and your block list.
+/// The download link works once the export is ready, poll the export to find out when. It's
+/// also sent by email.
+#[utoipa::path(
+    method(post),
+    path = "/",
+    responses(
+        (status = ACCEPTED, description = "Export started", body = ExportResponse),
+        (status = CONFLICT, description = "An export is already being built"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn create_export(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+) -> AxumResult<(StatusCode, Json<ExportResponse>)> {
+    let pending = export::Entity::find()
+        .filter(export::Column::UserId.eq(user.id))
+        .filter(export::Column::Status.eq(ExportStatus::Pending))
+        .into_partial_model::<ExportInfo>()
+        .one(&state.db)
+        .await?;
+
+    if pending.is_some() {
+        return Err(
+            AxumError::conflict(eyre!("An export is already being built"))
+                .with_code("export_in_progress"),
+        );
+    }
+
+    let download_token = generate_token();
+
+    // A unique index on pending exports catches two started at once
+    let export = export::ActiveModel {
+        user_id: Set(user.id),
+        status: Set(ExportStatus::Pending),
+        created_at: Set(Utc::now()),
+        download_token_hash: Set(Some(hash_token(&download_token))),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await
+    .map_err(|error| match error.sql_err() {
+        Some(SqlErr::UniqueConstraintViolation(_)) => {
+            AxumError::conflict(eyre!("An export is already being built"))
+                .with_code("export_in_progress")
+        }
+        _ => error.into(),
+    })?;
+
+    let response = ExportResponse {
+        id: export.id,
+        status: export.status,
+        created_at: export.created_at,
+        finished_at: export.finished_at,
+        download_url: Some(download_url(&state, &download_token)),
+        expires_at: export.expires_at,
+    };
+
+    tokio::spawn(run_export(state.clone(), export.id, download_token));
+
+    Ok((StatusCode::ACCEPTED, Json(response)))
+}
+
+/// Get export status
+#[utoipa::path(
+    method(get),
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "Export ID")
+    ),
+    responses(
+        (status = OK, description = "Success", body = ExportResponse),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn get_export(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<Json<ExportResponse>> {
+    let export = export::Entity::find_by_id(id)
+        .filter(export::Column::UserId.eq(user.id))
+        .into_partial_model::<ExportInfo>()
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("Export not found")))?;
+
+    Ok(Json(export.into()))
+}
@@ -0,0 +1,220 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, Query},
+};
+use axum_valid::Valid;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use http::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, Select};
+use serde::Serialize;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    entity::{follow, user},
+    errors::{AxumError, AxumResult, NotFoundError},
+    middlewares::UnauthorizedError,
+    routes::api::user::id::PublicUserResponse,
+    state::AppState,
+    util::{
+        blocks::has_blocked,
+        pagination::{Page, PageQuery, paginate},
+    },
+};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(follow_user, unfollow_user))
+        .routes(routes!(get_followers))
+        .routes(routes!(get_following))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ManyPublicUsersResponse {
+    pub users: Vec<PublicUserResponse>,
+
+    /// Pass as `cursor` to get the next page, missing on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Follow a user
+///
+/// Their public notes show up in the `following` feed. Following someone twice does nothing.
+#[utoipa::path(
+    method(post),
+    path = "/follow",
+    params(
+        ("id" = i32, Path, description = "User ID")
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Following"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = FORBIDDEN, description = "They blocked you"),
+        (status = UNPROCESSABLE_ENTITY, description = "Can't follow yourself"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Users"
+)]
+async fn follow_user(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<StatusCode> {
+    if id == user.id {
+        return Err(AxumError::unprocessable_entity(eyre!(
+            "You can't follow yourself"
+        )));
+    }
+
+    let followee = user::Entity::find_by_id(id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("User not found")))?;
+
+    if has_blocked(&state.db, followee.id, user.id).await? {
+        return Err(AxumError::forbidden(eyre!("You can't follow this user")));
+    }
+
+    let existing = follow::Entity::find_by_follower_followee((user.id, followee.id))
+        .one(&state.db)
+        .await?;
+
+    if existing.is_none() {
+        follow::ActiveModel {
+            follower_id: Set(user.id),
+            followee_id: Set(followee.id),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&state.db)
+        .await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Unfollow a user
+#[utoipa::path(
+    method(delete),
+    path = "/follow",
+    params(
+        ("id" = i32, Path, description = "User ID")
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Not following anymore"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Users"
+)]
+async fn unfollow_user(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<StatusCode> {
+    follow::Entity::delete_many()
+        .filter(follow::Column::FollowerId.eq(user.id))
+        .filter(follow::Column::FolloweeId.eq(id))
+        .exec(&state.db)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get a user's followers
+#[utoipa::path(
+    method(get),
+    path = "/followers",
+    params(
+        ("id" = i32, Path, description = "User ID"),
+        PageQuery
+    ),
+    responses(
+        (status = OK, description = "Success", body = ManyPublicUsersResponse),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Users"
+)]
+async fn get_followers(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+    Valid(Query(page)): Valid<Query<PageQuery>>,
+) -> AxumResult<Json<ManyPublicUsersResponse>> {
+    ensure_user_exists(&state, id).await?;
+
+    let select = follow::Entity::find().filter(follow::Column::FolloweeId.eq(id));
+    let follows = paginate_follows(&state, select, &page).await?;
+    let followers = follows
+        .items
+        .iter()
+        .map(|follow| follow.follower_id)
+        .collect();
+
+    Ok(Json(ManyPublicUsersResponse {
+        users: PublicUserResponse::response_from_ids(followers, &state.db, user.id).await?,
+        next_cursor: follows.next_cursor,
+    }))
+}
+
+/// Get the users someone follows
+#[utoipa::path(
+    method(get),
+    path = "/following",
+    params(
+        ("id" = i32, Path, description = "User ID"),
+        PageQuery
+    ),
+    responses(
+        (status = OK, description = "Success", body = ManyPublicUsersResponse),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Users"
+)]
+async fn get_following(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+    Valid(Query(page)): Valid<Query<PageQuery>>,
+) -> AxumResult<Json<ManyPublicUsersResponse>> {
+    ensure_user_exists(&state, id).await?;
+
+    let select = follow::Entity::find().filter(follow::Column::FollowerId.eq(id));
+    let follows = paginate_follows(&state, select, &page).await?;
+    let following = follows
+        .items
+        .iter()
+        .map(|follow| follow.followee_id)
+        .collect();
+
+    Ok(Json(ManyPublicUsersResponse {
+        users: PublicUserResponse::response_from_ids(following, &state.db, user.id).await?,
+        next_cursor: follows.next_cursor,
+    }))
+}
+
+async fn paginate_follows(
+    state: &AppState,
+    select: Select<follow::Entity>,
+    page: &PageQuery,
+) -> AxumResult<Page<follow::Model>> {
+    paginate(
+        &state.db,
+        select,
+        (follow::Column::CreatedAt, follow::Column::Id),
+        page,
+        |follow| (follow.created_at, follow.id),
+    )
+    .await
+}
+
+async fn ensure_user_exists(state: &AppState, id: i32) -> AxumResult<()> {
+    user::Entity::find_by_id(id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("User not found")))?;
+
+    Ok(())
+}
@@ -1,17 +1,32 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::{
-    entity::{note, user},
+    entity::{follow, note, user},
     errors::{AxumError, AxumResult},
     middlewares::UnauthorizedError,
-    routes::api::notes::ManyNotesResponse,
+    policy::{Action, authorize},
+    routes::api::notes::{ManyNotesResponse, paginate_notes},
     state::AppState,
+    storage::{PROFILE_PICTURES_PREFIX, new_key, sniff_content_type},
+    util::{
+        blocks::visible_authors,
+        downloads::{Download, serve_download},
+        pagination::PageQuery,
+    },
 };
 use axum::body::Bytes;
-use axum::{Extension, Json, extract::Path, response::IntoResponse};
+use axum::{
+    Extension, Json,
+    extract::{Path, Query},
+    response::Response,
+};
+use axum_valid::Valid;
 use chrono::NaiveDateTime;
+use color_eyre::Result;
 use color_eyre::eyre::eyre;
-use http::{HeaderMap, StatusCode, header};
+use http::{HeaderMap, StatusCode};
 use sea_orm::{ActiveModelTrait, ActiveValue::Set};
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect};
 use serde::Serialize;
 use utoipa::ToSchema;
 use utoipa_axum::{router::OpenApiRouter, routes};
@@ -28,20 +43,93 @@ pub fn routes() -> OpenApiRouter<AppState> {
 pub struct PublicUserResponse {
     pub id: i32,
     pub username: String,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub school: Option<String>,
+    pub class: Option<String>,
     pub created_at: NaiveDateTime,
     pub has_profile_picture: bool,
+    pub followers: i64,
+    pub following: i64,
+    /// Whether you follow this user
+    pub is_followed: bool,
 }
 
-impl From<user::Model> for PublicUserResponse {
-    fn from(user: user::Model) -> Self {
-        PublicUserResponse {
-            id: user.id,
-            username: user.username,
-            created_at: user.created_at,
-            has_profile_picture: user.profile_picture.is_some(),
-        }
+impl PublicUserResponse {
+    pub async fn response_from_array(
+        users: Vec<user::Model>,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> Result<Vec<PublicUserResponse>> {
+        let ids: Vec<i32> = users.iter().map(|user| user.id).collect();
+
+        let followers = count_follows(db, follow::Column::FolloweeId, &ids).await?;
+        let following = count_follows(db, follow::Column::FollowerId, &ids).await?;
+
+        let followed: HashSet<i32> = follow::Entity::find()
+            .select_only()
+            .column(follow::Column::FolloweeId)
+            .filter(follow::Column::FollowerId.eq(user_id))
+            .filter(follow::Column::FolloweeId.is_in(ids))
+            .into_tuple()
+            .all(db)
+            .await?
+            .into_iter()
+            .collect();
+
+        Ok(users
+            .into_iter()
+            .map(|user| PublicUserResponse {
+                id: user.id,
+                followers: followers.get(&user.id).copied().unwrap_or_default(),
+                following: following.get(&user.id).copied().unwrap_or_default(),
+                is_followed: followed.contains(&user.id),
+                username: user.username,
+                display_name: user.display_name,
+                bio: user.bio,
+                school: user.school,
+                class: user.class,
+                created_at: user.created_at,
+                has_profile_picture: user.profile_picture_key.is_some(),
+            })
+            .collect())
+    }
+
+    /// Loads the users with `ids`, keeping their order
+    pub async fn response_from_ids(
+        ids: Vec<i32>,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> Result<Vec<PublicUserResponse>> {
+        let mut users = user::Entity::find()
+            .filter(user::Column::Id.is_in(ids.iter().copied()))
+            .all(db)
+            .await?;
+        users.sort_by_key(|user| ids.iter().position(|id| *id == user.id));
+
+        Self::response_from_array(users, db, user_id).await
     }
 }
+
+/// Number of follows per user, grouped by `column`
+async fn count_follows(
+    db: &DatabaseConnection,
+    column: follow::Column,
+    ids: &[i32],
+) -> Result<HashMap<i32, i64>> {
+    let counts: Vec<(i32, i64)> = follow::Entity::find()
+        .select_only()
+        .column(column)
+        .column_as(follow::Column::Id.count(), "count")
+        .filter(column.is_in(ids.iter().copied()))
+        .group_by(column)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    Ok(counts.into_iter().collect())
+}
+
 /// Get user public info
 #[utoipa::path(
     method(get),
@@ -57,6 +145,7 @@ impl From<user::Model> for PublicUserResponse {
 )]
 async fn get_user(
     Extension(state): Extension<AppState>,
+    Extension(current_user): Extension<user::Model>,
     Path(id): Path<i32>,
 ) -> AxumResult<Json<PublicUserResponse>> {
     let user = user::Entity::find_by_id(id)
@@ -64,7 +153,11 @@ async fn get_user(
         .await?
         .ok_or_else(|| AxumError::not_found(eyre!("User not found")))?;
 
-    Ok(Json(user.into()))
+    let response = PublicUserResponse::response_from_array(vec![user], &state.db, current_user.id)
+        .await?
+        .remove(0);
+
+    Ok(Json(response))
 }
 
 /// Get user public notes
@@ -72,7 +165,8 @@ async fn get_user(
     method(get),
     path = "/notes",
     params(
-        ("id" = i32, Path, description = "User ID")
+        ("id" = i32, Path, description = "User ID"),
+        PageQuery
     ),
     responses(
         (status = OK, description = "Success", body = ManyNotesResponse),
@@ -84,16 +178,18 @@ async fn get_user_notes(
     Extension(state): Extension<AppState>,
     Path(id): Path<i32>,
     Extension(user): Extension<user::Model>,
+    Valid(Query(page)): Valid<Query<PageQuery>>,
 ) -> AxumResult<Json<ManyNotesResponse>> {
-    let notes = note::Entity::find()
+    let select = note::Entity::find()
         .filter(note::Column::UserId.eq(id))
         .filter(note::Column::Public.eq(true))
-        .order_by_desc(note::Column::CreatedAt)
-        .all(&state.db)
-        .await?;
+        .filter(note::Column::HiddenAt.is_null())
+        .filter(note::Column::DeletedAt.is_null())
+        .filter(visible_authors(user.id));
+    let notes = paginate_notes(&state.db, select, &page).await?;
 
     Ok(Json(
-        ManyNotesResponse::response_from_array(notes, &state.db, user.id).await?,
+        ManyNotesResponse::response_from_page(notes, &state.db, user.id).await?,
     ))
 }
 
@@ -105,8 +201,11 @@ async fn get_user_notes(
     ),
     responses(
         // OpenAPI can't easily express "raw bytes", we document `string` here
-        (status = OK, description = "Profile picture", content_type = "image/png", body = String),
+        (status = OK, description = "Profile picture", content_type = "image/*", body = String),
+        (status = PARTIAL_CONTENT, description = "The requested range", content_type = "image/*", body = String),
+        (status = NOT_MODIFIED, description = "Your cached copy is current"),
         (status = NOT_FOUND, description = "User or picture not found"),
+        (status = RANGE_NOT_SATISFIABLE, description = "The range is outside the picture"),
         (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
     ),
     tag = "Users"
@@ -114,24 +213,39 @@ async fn get_user_notes(
 async fn get_user_profile_picture(
     Extension(state): Extension<AppState>,
     Path(id): Path<i32>,
-) -> AxumResult<impl IntoResponse> {
+    headers: HeaderMap,
+) -> AxumResult<Response> {
     let user = user::Entity::find_by_id(id)
         .one(&state.db)
         .await?
         .ok_or_else(|| AxumError::not_found(eyre!("User not found")))?;
 
-    let bytes = match user.profile_picture {
-        Some(bytes) => bytes,
-        None => return Err(AxumError::not_found(eyre!("User has no profile picture"))),
+    let (Some(key), Some(size)) = (user.profile_picture_key, user.profile_picture_size) else {
+        return Err(AxumError::not_found(eyre!("User has no profile picture")));
     };
 
-    // If you store mime type separately, use that instead of hard-coding.
-    let mut headers = HeaderMap::new();
-    headers.insert(header::CONTENT_TYPE, "image/png".parse().unwrap());
-
-    Ok((StatusCode::OK, headers, bytes))
+    serve_download(
+        state.storage.as_ref(),
+        &headers,
+        Download {
+            storage_key: &key,
+            size: size as u64,
+            content_type: user
+                .profile_picture_content_type
+                .as_deref()
+                .unwrap_or("application/octet-stream"),
+            filename: None,
+            attachment: false,
+            // Pictures change under the same URL, so clients check the ETag every time
+            cache_control: "private, no-cache",
+        },
+    )
+    .await
 }
 
+/// Image types accepted as profile pictures, as sniffed from the uploaded bytes
+const PROFILE_PICTURE_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/webp", "image/gif"];
+
 #[utoipa::path(
     method(put),
     path = "/avatar",
@@ -140,10 +254,11 @@ async fn get_user_profile_picture(
     ),
     request_body(
         description = "Raw image bytes",
-        content_type = "image/png"
+        content_type = "image/*"
     ),
     responses(
         (status = NO_CONTENT, description = "Profile picture updated"),
+        (status = UNSUPPORTED_MEDIA_TYPE, description = "Not a PNG, JPEG, WebP or GIF image"),
         (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError),
         (status = NOT_FOUND, description = "User not found")
     ),
@@ -154,16 +269,8 @@ async fn set_user_profile_picture(
     Path(id): Path<i32>,
     // authenticated user injected by middleware
     Extension(current_user): Extension<user::Model>,
-    headers: HeaderMap,
     bytes: Bytes,
 ) -> AxumResult<StatusCode> {
-    // simple ownership check; adapt to your auth logic
-    if current_user.id != id {
-        return Err(AxumError::unauthorized(eyre!(
-            "You cannot change another user's profile picture"
-        )));
-    }
-
     // Optional: size limit to avoid abuse
     const MAX_SIZE: usize = 2 * 1024 * 1024; // 2 MB
     if bytes.len() > MAX_SIZE {
@@ -175,18 +282,28 @@ async fn set_user_profile_picture(
         .await?
         .ok_or_else(|| AxumError::not_found(eyre!("User not found")))?;
 
-    let content_type = headers
-        .get(header::CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| AxumError::bad_request(eyre!("Missing Content-Type")))?;
+    authorize(&current_user, &user_model, Action::Edit)?;
 
-    if content_type != "image/png" {
-        return Err(AxumError::bad_request(eyre!("Only image/png is allowed")));
+    // The declared Content-Type is up to the client, only the bytes themselves are trusted
+    let picture_type = sniff_content_type(&bytes);
+    if !PROFILE_PICTURE_TYPES.contains(&picture_type) {
+        return Err(AxumError::unsupported_media_type(eyre!(
+            "Only PNG, JPEG, WebP and GIF images are allowed"
+        )));
     }
+    let size = bytes.len() as i64;
+
+    let key = new_key(PROFILE_PICTURES_PREFIX);
+    state.storage.put(&key, bytes).await?;
 
+    let old_key = user_model.profile_picture_key.clone();
     let mut active: user::ActiveModel = user_model.into();
-    active.profile_picture = Set(Some(bytes.to_vec()));
+    active.profile_picture_key = Set(Some(key));
+    active.profile_picture_content_type = Set(Some(picture_type.to_string()));
+    active.profile_picture_size = Set(Some(size));
     active.update(&state.db).await?;
 
+    state.storage.delete_all(old_key.as_slice()).await;
+
     Ok(StatusCode::NO_CONTENT)
 }
@@ -1,17 +1,73 @@
+mod blocks;
+mod export;
+mod follows;
 mod id;
+mod password;
+mod sessions;
+mod tokens;
+mod two_factor;
 
-use axum::{Extension, Json};
-use chrono::NaiveDateTime;
-use serde::Serialize;
+use axum::{Extension, Json, middleware};
+use axum_valid::Valid;
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use color_eyre::eyre::eyre;
+use http::StatusCode;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
 use utoipa::ToSchema;
 use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::{Validate, ValidationError};
 
-use crate::{entity::user, errors::AxumResult, middlewares::UnauthorizedError, state::AppState};
+use crate::{
+    entity::{
+        email_verification,
+        token::{self, Scope},
+        user::{self, Role},
+    },
+    errors::{AxumError, AxumResult, ConstraintError},
+    mailer::Email,
+    middlewares::{ScopeRule, UnauthorizedError, require_scope},
+    state::AppState,
+    util::{
+        passwords::verify_password,
+        two_factor::verify_second_factor,
+        usernames::{is_username_available, reserve_old_username, validate_username},
+        verification::send_verification_email,
+    },
+};
 
 pub fn routes() -> OpenApiRouter<AppState> {
     OpenApiRouter::new()
-        .routes(routes!(get_current_user))
-        .nest("/{id}", id::routes())
+        .routes(routes!(
+            get_current_user,
+            update_current_user,
+            delete_current_user
+        ))
+        .routes(routes!(resend_verification_email))
+        .nest("/password", password::routes())
+        .nest("/sessions", sessions::routes())
+        .nest("/2fa", two_factor::routes())
+        .nest("/tokens", tokens::routes())
+        .nest("/export", export::routes())
+        .nest("/blocks", blocks::routes())
+        .layer(middleware::from_fn_with_state(
+            ScopeRule::SESSION_ONLY,
+            require_scope,
+        ))
+        // Public profiles and notes are readable like any other notes
+        .nest(
+            "/{id}",
+            id::routes()
+                .merge(follows::routes())
+                .merge(blocks::target_routes())
+                .layer(middleware::from_fn_with_state(
+                    ScopeRule::read_only(Scope::NotesRead),
+                    require_scope,
+                )),
+        )
 }
 
 #[derive(Serialize, ToSchema)]
@@ -19,8 +75,15 @@ pub struct UserResponse {
     pub id: i32,
 
     pub username: String,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub school: Option<String>,
+    pub class: Option<String>,
+    pub preferred_language: Option<String>,
 
     pub email: String,
+    pub email_verified: bool,
+    pub role: Role,
     pub created_at: NaiveDateTime,
     pub has_profile_picture: bool,
 }
@@ -30,9 +93,16 @@ impl From<user::Model> for UserResponse {
         UserResponse {
             id: user.id,
             username: user.username,
+            display_name: user.display_name,
+            bio: user.bio,
+            school: user.school,
+            class: user.class,
+            preferred_language: user.preferred_language,
             email: user.email,
+            email_verified: user.email_verified_at.is_some(),
+            role: user.role,
             created_at: user.created_at,
-            has_profile_picture: user.profile_picture.is_some(),
+            has_profile_picture: user.profile_picture_key.is_some(),
         }
     }
 }
@@ -52,3 +122,314 @@ async fn get_current_user(
 ) -> AxumResult<Json<UserResponse>> {
     Ok(Json(user.into()))
 }
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct UpdateUserRequest {
+    /// The old username stays reserved for you for a while
+    #[validate(custom(function = "validate_username"))]
+    pub username: Option<String>,
+
+    /// The email changes once the link sent to the new address is opened
+    #[validate(email, length(max = 128))]
+    pub email: Option<String>,
+
+    /// Current password, required to change the email
+    #[validate(length(min = 1, max = 128))]
+    pub password: Option<String>,
+
+    /// An empty string clears the field, like for the other profile fields
+    #[validate(length(max = 64))]
+    pub display_name: Option<String>,
+
+    #[validate(length(max = 1000))]
+    pub bio: Option<String>,
+
+    #[validate(length(max = 128))]
+    pub school: Option<String>,
+
+    #[validate(length(max = 64))]
+    pub class: Option<String>,
+
+    /// BCP 47 language tag, like `en` or `pt-BR`
+    #[validate(custom(function = "validate_language"))]
+    pub preferred_language: Option<String>,
+}
+
+fn validate_language(language: &str) -> Result<(), ValidationError> {
+    let valid = language.len() <= 35
+        && language.split('-').all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+
+    if language.is_empty() || valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("language"))
+    }
+}
+
+/// Trims a profile field, treating blank as unset
+fn profile_field(value: String) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Update your profile
+///
+/// Only the fields that are present are changed
+#[utoipa::path(
+    method(patch),
+    path = "/",
+    responses(
+        (status = OK, description = "Success", body = UserResponse),
+        (status = CONFLICT, description = "Username or email already taken", body = ConstraintError),
+        (status = FORBIDDEN, description = "Password is incorrect"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn update_current_user(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Valid(Json(body)): Valid<Json<UpdateUserRequest>>,
+) -> AxumResult<Json<UserResponse>> {
+    let new_username = body.username.filter(|username| *username != user.username);
+    let new_email = body.email.filter(|email| *email != user.email);
+
+    if let Some(username) = &new_username
+        && !is_username_available(&state.db, username, Some(user.id)).await?
+    {
+        return Err(AxumError::conflict(eyre!("username is already taken"))
+            .with_code("already_exists")
+            .with_field("username"));
+    }
+
+    if let Some(email) = &new_email {
+        let password = body.password.as_deref().unwrap_or_default();
+        if !verify_password(password, &user.password) {
+            return Err(AxumError::forbidden(eyre!("Password is incorrect")));
+        }
+
+        let taken = user::Entity::find_by_email(email.clone())
+            .one(&state.db)
+            .await?
+            .is_some();
+        if taken {
+            return Err(AxumError::conflict(eyre!("email is already taken"))
+                .with_code("already_exists")
+                .with_field("email"));
+        }
+    }
+
+    let mut model: user::ActiveModel = user.clone().into();
+    if let Some(display_name) = body.display_name {
+        model.display_name = Set(profile_field(display_name));
+    }
+    if let Some(bio) = body.bio {
+        model.bio = Set(profile_field(bio));
+    }
+    if let Some(school) = body.school {
+        model.school = Set(profile_field(school));
+    }
+    if let Some(class) = body.class {
+        model.class = Set(profile_field(class));
+    }
+    if let Some(preferred_language) = body.preferred_language {
+        model.preferred_language = Set(profile_field(preferred_language));
+    }
+
+    let txn = state.db.begin().await?;
+    if let Some(username) = new_username {
+        reserve_old_username(
+            &txn,
+            &user,
+            &username,
+            state.settings.auth.username_reservation_period,
+        )
+        .await?;
+        model.username = Set(username);
+    }
+    let updated = model.update(&txn).await?;
+    txn.commit().await?;
+
+    if let Some(email) = new_email {
+        // The profile is already saved, a failed email can be retried by resending
+        if let Err(error) = send_verification_email(&state, &updated, &email).await {
+            warn!(error = ?error, "Failed to send verification email");
+        }
+
+        let notice = Email {
+            to: user.email,
+            subject: "Your Mathisi email is being changed".to_string(),
+            body: format!(
+                "Hi {},\n\nSomeone asked to change the email of your account to {}. It changes once the new address is verified.\n\nIf this wasn't you, reset your password and sign out your other sessions.\n",
+                updated.username, email,
+            ),
+        };
+
+        // The verification link went out, the notice is only a safety net
+        if let Err(error) = state.mailer.send(notice).await {
+            warn!(error = ?error, "Failed to send email change notice");
+        }
+    }
+
+    Ok(Json(updated.into()))
+}
+
+/// Resend the email verification link
+///
+/// Goes to the new address while an email change is pending
+#[utoipa::path(
+    method(post),
+    path = "/verify-email",
+    responses(
+        (status = NO_CONTENT, description = "Verification email sent"),
+        (status = CONFLICT, description = "Email already verified"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn resend_verification_email(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+) -> AxumResult<StatusCode> {
+    let pending_email = email_verification::Entity::find()
+        .filter(email_verification::Column::UserId.eq(user.id))
+        .filter(email_verification::Column::Email.ne(&user.email))
+        .one(&state.db)
+        .await?
+        .map(|verification| verification.email);
+
+    let email = match pending_email {
+        Some(email) => email,
+        None if user.email_verified_at.is_some() => {
+            return Err(AxumError::conflict(eyre!("Email already verified")));
+        }
+        None => user.email.clone(),
+    };
+
+    send_verification_email(&state, &user, &email).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct DeleteAccountRequest {
+    #[validate(length(min = 1, max = 128))]
+    pub password: String,
+
+    /// TOTP or recovery code, required when two-factor authentication is enabled
+    #[validate(length(min = 1, max = 32))]
+    pub code: Option<String>,
+
+    /// Keep public notes under a "deleted user" placeholder instead of deleting them
+    #[serde(default)]
+    pub keep_public_notes: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeleteAccountResponse {
+    /// Logging in before this cancels the deletion
+    pub deletion_scheduled_at: NaiveDateTime,
+}
+
+/// Delete your account
+///
+/// Signs out everywhere and permanently deletes the account and its data after a grace period.
+/// Logging in again before then cancels the deletion. Accounts created through a login provider
+/// can set a password with a password reset first.
+#[utoipa::path(
+    method(delete),
+    path = "/",
+    responses(
+        (status = ACCEPTED, description = "Deletion scheduled", body = DeleteAccountResponse),
+        (status = FORBIDDEN, description = "Password or two-factor code is incorrect"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn delete_current_user(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Valid(Json(body)): Valid<Json<DeleteAccountRequest>>,
+) -> AxumResult<(StatusCode, Json<DeleteAccountResponse>)> {
+    if !verify_password(&body.password, &user.password) {
+        return Err(AxumError::forbidden(eyre!("Password is incorrect")));
+    }
+
+    if user.totp_enabled_at.is_some() {
+        let code = body.code.as_deref().unwrap_or_default();
+
+        if !verify_second_factor(&state, &user, code).await? {
+            return Err(AxumError::forbidden(eyre!("Invalid code")).with_code("invalid_code"));
+        }
+    }
+
+    let grace_period = TimeDelta::seconds(state.settings.auth.account_deletion_grace_period);
+    let deletion_scheduled_at = Utc::now().naive_utc() + grace_period;
+
+    let user_id = user.id;
+    let mut model: user::ActiveModel = user.clone().into();
+    model.deletion_scheduled_at = Set(Some(deletion_scheduled_at));
+    model.deletion_keeps_public_notes = Set(body.keep_public_notes);
+    model.update(&state.db).await?;
+
+    token::Entity::delete_many()
+        .filter(token::Column::UserId.eq(user_id))
+        .exec(&state.db)
+        .await?;
+
+    let email = Email {
+        to: user.email,
+        subject: "Your Mathisi account will be deleted".to_string(),
+        body: format!(
+            "Hi {},\n\nYour account and its data will be permanently deleted on {} UTC.\n\nChanged your mind? Log in before then and the deletion is cancelled.\n",
+            user.username,
+            deletion_scheduled_at.format("%Y-%m-%d %H:%M"),
+        ),
+    };
+
+    // The deletion is scheduled either way, the email is only a courtesy
+    if let Err(error) = state.mailer.send(email).await {
+        warn!(error = ?error, "Failed to send account deletion email");
+    }
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(DeleteAccountResponse {
+            deletion_scheduled_at,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_language;
+
+    #[test]
+    fn accepts_language_tags() {
+        for language in ["en", "de-AT", "zh-Hant-TW", "sl-rozaj-biske", "x-private1"] {
+            assert!(validate_language(language).is_ok(), "{language}");
+        }
+    }
+
+    #[test]
+    fn accepts_empty_to_clear() {
+        assert!(validate_language("").is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_tags() {
+        for language in ["en-", "-en", "en--US", "en_US", "en US", "ëñ", "abcdefghi"] {
+            assert!(validate_language(language).is_err(), "{language}");
+        }
+    }
+
+    #[test]
+    fn rejects_overlong_tags() {
+        let language = ["abcdefgh"; 4].join("-");
+        assert_eq!(language.len(), 35);
+        assert!(validate_language(&language).is_ok());
+        assert!(validate_language(&format!("{language}-x")).is_err());
+    }
+}
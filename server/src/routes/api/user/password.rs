@@ -0,0 +1,75 @@
+use axum::{Extension, Json};
+use axum_valid::Valid;
+use color_eyre::eyre::eyre;
+use http::StatusCode;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, TransactionTrait,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
+
+use crate::{
+    entity::{token, user},
+    errors::{AxumError, AxumResult},
+    middlewares::UnauthorizedError,
+    state::AppState,
+    util::passwords::{hash_password, verify_password},
+};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(change_password))
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct ChangePasswordRequest {
+    #[validate(length(min = 1, max = 128))]
+    pub old_password: String,
+
+    #[validate(length(min = 1, max = 128))]
+    pub new_password: String,
+}
+
+/// Change password
+///
+/// Signs out every other session
+#[utoipa::path(
+    method(post),
+    path = "/",
+    responses(
+        (status = NO_CONTENT, description = "Password changed"),
+        (status = FORBIDDEN, description = "Old password is incorrect"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn change_password(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Extension(current): Extension<token::Model>,
+    Valid(Json(body)): Valid<Json<ChangePasswordRequest>>,
+) -> AxumResult<StatusCode> {
+    if !verify_password(&body.old_password, &user.password) {
+        return Err(AxumError::forbidden(eyre!("Old password is incorrect")));
+    }
+
+    let password_hash = hash_password(&body.new_password)?;
+
+    let user_id = user.id;
+    let txn = state.db.begin().await?;
+
+    let mut user: user::ActiveModel = user.into();
+    user.password = Set(password_hash);
+    user.update(&txn).await?;
+
+    token::Entity::delete_many()
+        .filter(token::Column::UserId.eq(user_id))
+        .filter(token::Column::Id.ne(current.id))
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
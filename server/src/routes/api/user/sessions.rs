@@ -0,0 +1,143 @@
+use axum::{Extension, Json, extract::Path};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::eyre;
+use http::StatusCode;
+use sea_orm::{ColumnTrait, EntityTrait, ModelTrait, QueryFilter, QueryOrder};
+use serde::Serialize;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    entity::{token, user},
+    errors::{AxumError, AxumResult, NotFoundError},
+    middlewares::UnauthorizedError,
+    state::AppState,
+};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_sessions, revoke_other_sessions))
+        .routes(routes!(revoke_session))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SessionResponse {
+    pub id: i32,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Whether this is the session used to make the request
+    pub current: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ManySessionsResponse {
+    pub sessions: Vec<SessionResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RevokeSessionsResponse {
+    pub revoked: u64,
+}
+
+/// List your active sessions
+///
+/// Personal access tokens are listed separately
+#[utoipa::path(
+    method(get),
+    path = "/",
+    responses(
+        (status = OK, description = "Success", body = ManySessionsResponse),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn get_sessions(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Extension(current): Extension<token::Model>,
+) -> AxumResult<Json<ManySessionsResponse>> {
+    let sessions = token::Entity::find()
+        .filter(token::Column::UserId.eq(user.id))
+        .filter(token::Column::Scopes.is_null())
+        .order_by_desc(token::Column::CreatedAt)
+        .all(&state.db)
+        .await?
+        .into_iter()
+        .map(|token| SessionResponse {
+            id: token.id,
+            created_at: token.created_at,
+            last_used_at: token.last_used_at,
+            expires_at: token.expires_at,
+            current: token.id == current.id,
+        })
+        .collect();
+
+    Ok(Json(ManySessionsResponse { sessions }))
+}
+
+/// Revoke all sessions except the current one
+#[utoipa::path(
+    method(delete),
+    path = "/",
+    responses(
+        (status = OK, description = "Success", body = RevokeSessionsResponse),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn revoke_other_sessions(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Extension(current): Extension<token::Model>,
+) -> AxumResult<Json<RevokeSessionsResponse>> {
+    let result = token::Entity::delete_many()
+        .filter(token::Column::UserId.eq(user.id))
+        .filter(token::Column::Id.ne(current.id))
+        .filter(token::Column::Scopes.is_null())
+        .exec(&state.db)
+        .await?;
+
+    Ok(Json(RevokeSessionsResponse {
+        revoked: result.rows_affected,
+    }))
+}
+
+/// Revoke a single session
+#[utoipa::path(
+    method(delete),
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "Session ID")
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Session revoked"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNPROCESSABLE_ENTITY, description = "Cannot revoke the current session"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn revoke_session(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Extension(current): Extension<token::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<StatusCode> {
+    if id == current.id {
+        return Err(AxumError::unprocessable_entity(eyre!(
+            "Use /api/logout to revoke the current session"
+        )));
+    }
+
+    let token = token::Entity::find_by_id(id)
+        .filter(token::Column::UserId.eq(user.id))
+        .filter(token::Column::Scopes.is_null())
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("Session not found")))?;
+
+    token.delete(&state.db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
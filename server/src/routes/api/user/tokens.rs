@@ -0,0 +1,189 @@
+use axum::{Extension, Json, extract::Path};
+use axum_valid::Valid;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::eyre;
+use http::StatusCode;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, ModelTrait, QueryFilter,
+    QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
+
+use crate::{
+    entity::{
+        token::{self, Scope},
+        user,
+    },
+    errors::{AxumError, AxumResult, NotFoundError},
+    middlewares::UnauthorizedError,
+    state::AppState,
+    util::tokens::{generate_token, hash_token},
+};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_tokens, create_token))
+        .routes(routes!(revoke_token))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PersonalTokenResponse {
+    pub id: i32,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl From<token::Model> for PersonalTokenResponse {
+    fn from(token: token::Model) -> Self {
+        PersonalTokenResponse {
+            id: token.id,
+            scopes: token.scopes(),
+            name: token.name.unwrap_or_default(),
+            created_at: token.created_at,
+            last_used_at: token.last_used_at,
+            expires_at: token.expires_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ManyPersonalTokensResponse {
+    pub tokens: Vec<PersonalTokenResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedPersonalTokenResponse {
+    /// Shown only once, send it in the `Authorization` header
+    pub token: String,
+
+    #[serde(flatten)]
+    pub details: PersonalTokenResponse,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct CreatePersonalTokenRequest {
+    #[validate(length(min = 1, max = 64))]
+    pub name: String,
+
+    #[validate(length(min = 1))]
+    pub scopes: Vec<Scope>,
+
+    /// Leave out for a token that doesn't expire
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// List your personal access tokens
+#[utoipa::path(
+    method(get),
+    path = "/",
+    responses(
+        (status = OK, description = "Success", body = ManyPersonalTokensResponse),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn get_tokens(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+) -> AxumResult<Json<ManyPersonalTokensResponse>> {
+    let tokens = token::Entity::find()
+        .filter(token::Column::UserId.eq(user.id))
+        .filter(token::Column::Scopes.is_not_null())
+        .order_by_desc(token::Column::CreatedAt)
+        .all(&state.db)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+
+    Ok(Json(ManyPersonalTokensResponse { tokens }))
+}
+
+/// Create a personal access token
+///
+/// Personal access tokens only work for the routes their scopes cover and don't time out when
+/// unused
+#[utoipa::path(
+    method(post),
+    path = "/",
+    responses(
+        (status = OK, description = "Success", body = CreatedPersonalTokenResponse),
+        (status = UNPROCESSABLE_ENTITY, description = "Expiry is in the past"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn create_token(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Valid(Json(body)): Valid<Json<CreatePersonalTokenRequest>>,
+) -> AxumResult<Json<CreatedPersonalTokenResponse>> {
+    let now = Utc::now();
+
+    if body.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(AxumError::unprocessable_entity(eyre!(
+            "Expiry must be in the future"
+        )));
+    }
+
+    let mut scopes: Vec<&str> = body.scopes.iter().map(AsRef::as_ref).collect();
+    scopes.sort_unstable();
+    scopes.dedup();
+
+    let token = generate_token();
+
+    let model = token::ActiveModel {
+        user_id: Set(user.id),
+        token_hash: Set(hash_token(&token)),
+        created_at: Set(now),
+        expires_at: Set(body.expires_at),
+        last_used_at: Set(now),
+        name: Set(Some(body.name)),
+        scopes: Set(Some(scopes.join(" "))),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+
+    Ok(Json(CreatedPersonalTokenResponse {
+        token,
+        details: model.into(),
+    }))
+}
+
+/// Revoke a personal access token
+#[utoipa::path(
+    method(delete),
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "Token ID")
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Token revoked"),
+        (status = NOT_FOUND, description = "Not found", body = NotFoundError),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn revoke_token(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Path(id): Path<i32>,
+) -> AxumResult<StatusCode> {
+    let token = token::Entity::find_by_id(id)
+        .filter(token::Column::UserId.eq(user.id))
+        .filter(token::Column::Scopes.is_not_null())
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AxumError::not_found(eyre!("Token not found")))?;
+
+    token.delete(&state.db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,280 @@
+use axum::{Extension, Json};
+use axum_valid::Valid;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use http::StatusCode;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
+
+use crate::{
+    entity::{recovery_code, user},
+    errors::{AxumError, AxumResult},
+    middlewares::UnauthorizedError,
+    state::AppState,
+    util::{
+        passwords::verify_password,
+        two_factor::{
+            generate_totp_secret, otpauth_uri, regenerate_recovery_codes, verify_second_factor,
+            verify_totp,
+        },
+    },
+};
+
+pub fn routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_two_factor_status))
+        .routes(routes!(start_totp_enrollment, disable_totp))
+        .routes(routes!(confirm_totp_enrollment))
+        .routes(routes!(replace_recovery_codes))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TwoFactorStatusResponse {
+    pub enabled: bool,
+    pub recovery_codes_remaining: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TotpEnrollmentResponse {
+    /// Base32 secret, for entering into the authenticator app by hand
+    pub secret: String,
+
+    /// `otpauth://` URI, usually shown as a QR code
+    pub otpauth_uri: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RecoveryCodesResponse {
+    /// Shown only once, each code can be used a single time instead of a TOTP code
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct ConfirmTotpRequest {
+    #[validate(length(min = 1, max = 16))]
+    pub code: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct PasswordConfirmation {
+    #[validate(length(min = 1, max = 128))]
+    pub password: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct DisableTotpRequest {
+    #[validate(length(min = 1, max = 128))]
+    pub password: String,
+
+    /// TOTP or recovery code, required once enrollment was confirmed
+    #[validate(length(min = 1, max = 32))]
+    pub code: Option<String>,
+}
+
+/// Get two-factor authentication status
+#[utoipa::path(
+    method(get),
+    path = "/",
+    responses(
+        (status = OK, description = "Success", body = TwoFactorStatusResponse),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn get_two_factor_status(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+) -> AxumResult<Json<TwoFactorStatusResponse>> {
+    let recovery_codes_remaining = recovery_code::Entity::find()
+        .filter(recovery_code::Column::UserId.eq(user.id))
+        .count(&state.db)
+        .await?;
+
+    Ok(Json(TwoFactorStatusResponse {
+        enabled: user.totp_enabled_at.is_some(),
+        recovery_codes_remaining,
+    }))
+}
+
+/// Start TOTP enrollment
+///
+/// Generates a new secret. Two-factor login isn't required until the enrollment is confirmed.
+#[utoipa::path(
+    method(post),
+    path = "/totp",
+    responses(
+        (status = OK, description = "Success", body = TotpEnrollmentResponse),
+        (status = CONFLICT, description = "Two-factor authentication is already enabled"),
+        (status = FORBIDDEN, description = "Password is incorrect"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn start_totp_enrollment(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Valid(Json(body)): Valid<Json<PasswordConfirmation>>,
+) -> AxumResult<Json<TotpEnrollmentResponse>> {
+    if !verify_password(&body.password, &user.password) {
+        return Err(AxumError::forbidden(eyre!("Password is incorrect")));
+    }
+
+    if user.totp_enabled_at.is_some() {
+        return Err(AxumError::conflict(eyre!(
+            "Two-factor authentication is already enabled"
+        )));
+    }
+
+    let secret = generate_totp_secret();
+    let otpauth_uri = otpauth_uri(&secret, &state.settings.auth.totp_issuer, &user.username)?;
+
+    let mut user: user::ActiveModel = user.into();
+    user.totp_secret = Set(Some(secret.clone()));
+    user.totp_last_step = Set(None);
+    user.update(&state.db).await?;
+
+    Ok(Json(TotpEnrollmentResponse {
+        secret,
+        otpauth_uri,
+    }))
+}
+
+/// Confirm TOTP enrollment
+///
+/// Enables two-factor authentication once the authenticator app produces a valid code
+#[utoipa::path(
+    method(post),
+    path = "/totp/confirm",
+    responses(
+        (status = OK, description = "Success", body = RecoveryCodesResponse),
+        (status = CONFLICT, description = "Two-factor authentication is already enabled"),
+        (status = UNPROCESSABLE_ENTITY, description = "Invalid code or no enrollment started"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn confirm_totp_enrollment(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Valid(Json(body)): Valid<Json<ConfirmTotpRequest>>,
+) -> AxumResult<Json<RecoveryCodesResponse>> {
+    if user.totp_enabled_at.is_some() {
+        return Err(AxumError::conflict(eyre!(
+            "Two-factor authentication is already enabled"
+        )));
+    }
+
+    if user.totp_secret.is_none() {
+        return Err(AxumError::unprocessable_entity(eyre!(
+            "Start TOTP enrollment first"
+        )));
+    }
+
+    if !verify_totp(&state, &user, body.code.trim()).await? {
+        return Err(
+            AxumError::unprocessable_entity(eyre!("Invalid code")).with_code("invalid_code")
+        );
+    }
+
+    let user_id = user.id;
+    let user = user::ActiveModel {
+        id: Set(user_id),
+        totp_enabled_at: Set(Some(Utc::now().naive_utc())),
+        ..Default::default()
+    };
+
+    let txn = state.db.begin().await?;
+    user.update(&txn).await?;
+    let recovery_codes = regenerate_recovery_codes(&txn, user_id).await?;
+    txn.commit().await?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// Disable TOTP
+///
+/// Also cancels an enrollment that wasn't confirmed yet.
+#[utoipa::path(
+    method(delete),
+    path = "/totp",
+    responses(
+        (status = NO_CONTENT, description = "Two-factor authentication disabled"),
+        (status = FORBIDDEN, description = "Password or code is incorrect"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn disable_totp(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Valid(Json(body)): Valid<Json<DisableTotpRequest>>,
+) -> AxumResult<StatusCode> {
+    if !verify_password(&body.password, &user.password) {
+        return Err(AxumError::forbidden(eyre!("Password is incorrect")));
+    }
+
+    // Otherwise a stolen session and password would be enough to take the account over
+    if user.totp_enabled_at.is_some() {
+        let code = body.code.as_deref().unwrap_or_default();
+
+        if !verify_second_factor(&state, &user, code).await? {
+            return Err(AxumError::forbidden(eyre!("Invalid code")).with_code("invalid_code"));
+        }
+    }
+
+    let user_id = user.id;
+    let mut user: user::ActiveModel = user.into();
+    user.totp_secret = Set(None);
+    user.totp_enabled_at = Set(None);
+    user.totp_last_step = Set(None);
+    user.update(&state.db).await?;
+
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(&state.db)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Replace recovery codes
+///
+/// Invalidates all previous recovery codes
+#[utoipa::path(
+    method(post),
+    path = "/recovery-codes",
+    responses(
+        (status = OK, description = "Success", body = RecoveryCodesResponse),
+        (status = FORBIDDEN, description = "Password is incorrect"),
+        (status = UNPROCESSABLE_ENTITY, description = "Two-factor authentication is not enabled"),
+        (status = UNAUTHORIZED, description = "Unauthorized", body = UnauthorizedError)
+    ),
+    tag = "Auth"
+)]
+async fn replace_recovery_codes(
+    Extension(state): Extension<AppState>,
+    Extension(user): Extension<user::Model>,
+    Valid(Json(body)): Valid<Json<PasswordConfirmation>>,
+) -> AxumResult<Json<RecoveryCodesResponse>> {
+    if !verify_password(&body.password, &user.password) {
+        return Err(AxumError::forbidden(eyre!("Password is incorrect")));
+    }
+
+    if user.totp_enabled_at.is_none() {
+        return Err(AxumError::unprocessable_entity(eyre!(
+            "Two-factor authentication is not enabled"
+        )));
+    }
+
+    let txn = state.db.begin().await?;
+    let recovery_codes = regenerate_recovery_codes(&txn, user.id).await?;
+    txn.commit().await?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
@@ -1,13 +1,17 @@
 use std::sync::Arc;
 
 use async_openai::config::OpenAIConfig;
+use redis::aio::ConnectionManager;
 use sea_orm::DatabaseConnection;
 
-use crate::settings::Settings;
+use crate::{mailer::Mailer, settings::Settings, storage::Storage};
 
 #[derive(Clone)]
 pub struct AppState {
     pub settings: Arc<Settings>,
     pub db: DatabaseConnection,
+    pub redis: ConnectionManager,
     pub ai: async_openai::Client<OpenAIConfig>,
+    pub mailer: Arc<dyn Mailer>,
+    pub storage: Arc<dyn Storage>,
 }
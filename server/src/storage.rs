@@ -0,0 +1,80 @@
+pub mod local;
+pub mod migrate;
+pub mod s3;
+
+use std::ops::Range;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use color_eyre::Result;
+use futures::{TryStreamExt, stream::BoxStream};
+use tracing::warn;
+
+use crate::util::tokens::generate_token;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+/// Key prefix for uploaded files
+pub const FILES_PREFIX: &str = "files";
+
+/// Key prefix for profile pictures
+pub const PROFILE_PICTURES_PREFIX: &str = "profile-pictures";
+
+/// Key prefix for personal data export archives
+pub const EXPORTS_PREFIX: &str = "exports";
+
+pub type BlobStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+/// Keeps the contents of uploaded files, profile pictures and data exports. The database only
+/// stores their keys.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn put(&self, key: &str, data: Bytes) -> Result<()>;
+
+    /// Like [`Storage::put`], for blobs too large to hold in memory. Nothing is stored when `data`
+    /// fails.
+    async fn put_stream(&self, key: &str, data: BlobStream) -> Result<()>;
+
+    /// Streams the blob, or only the bytes in `range`, which must lie within it
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<BlobStream>;
+
+    /// Deleting a blob that doesn't exist succeeds
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+impl dyn Storage + '_ {
+    /// Reads a whole blob into memory
+    pub async fn read(&self, key: &str) -> Result<Vec<u8>> {
+        let data = self
+            .get(key, None)
+            .await?
+            .try_fold(Vec::new(), |mut data, chunk| async move {
+                data.extend_from_slice(&chunk);
+                Ok(data)
+            })
+            .await?;
+
+        Ok(data)
+    }
+
+    /// Removes blobs whose rows are already gone. Failures only leave unreferenced blobs behind,
+    /// so they're logged instead of returned.
+    pub async fn delete_all(&self, keys: &[String]) {
+        for key in keys {
+            if let Err(error) = self.delete(key).await {
+                warn!(key, error = ?error, "Failed to delete blob");
+            }
+        }
+    }
+}
+
+/// Guesses a blob's MIME type from its first bytes
+pub fn sniff_content_type(data: &[u8]) -> &'static str {
+    infer::get(data).map_or("application/octet-stream", |kind| kind.mime_type())
+}
+
+/// A fresh key for a new blob
+pub fn new_key(prefix: &str) -> String {
+    format!("{prefix}/{}", generate_token())
+}
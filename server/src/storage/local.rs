@@ -0,0 +1,100 @@
+use std::{
+    io::{ErrorKind, SeekFrom},
+    ops::Range,
+    path::{Component, Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use color_eyre::{Result, eyre::bail};
+use futures::StreamExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use super::{BlobStream, Storage};
+
+/// Keeps blobs as files under a directory, one per key
+pub struct LocalStorage {
+    directory: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        let key = Path::new(key);
+
+        if !key
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            bail!("Invalid storage key {key:?}");
+        }
+
+        Ok(self.directory.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: Bytes) -> Result<()> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Readers never see a half written blob
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, &path).await?;
+
+        Ok(())
+    }
+
+    async fn put_stream(&self, key: &str, data: BlobStream) -> Result<()> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let partial = path.with_extension("partial");
+        let written = async {
+            let mut file = tokio::fs::File::create(&partial).await?;
+            tokio::io::copy(&mut StreamReader::new(data), &mut file).await?;
+            file.sync_all().await
+        }
+        .await;
+
+        if let Err(error) = written {
+            // Missing when creating it was what failed
+            tokio::fs::remove_file(&partial).await.ok();
+            return Err(error.into());
+        }
+        tokio::fs::rename(&partial, &path).await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<BlobStream> {
+        let mut file = tokio::fs::File::open(self.path(key)?).await?;
+
+        let Some(range) = range else {
+            return Ok(ReaderStream::new(file).boxed());
+        };
+
+        file.seek(SeekFrom::Start(range.start)).await?;
+
+        Ok(ReaderStream::new(file.take(range.end - range.start)).boxed())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use color_eyre::Result;
+use futures::StreamExt;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use tracing::info;
+
+use crate::migrations::{column_exists, column_nullable};
+
+use super::{
+    EXPORTS_PREFIX, FILES_PREFIX, PROFILE_PICTURES_PREFIX, Storage, new_key, sniff_content_type,
+};
+
+/// Moves blobs that older versions kept in bytea columns into `storage`, then drops those columns.
+/// Runs before schema sync. Once the columns are gone this does nothing, and an interrupted run
+/// picks up where it stopped.
+pub async fn migrate_database_blobs(db: &DatabaseConnection, storage: &dyn Storage) -> Result<()> {
+    if column_exists(db, "files", "data").await? {
+        db.execute_unprepared(
+            "ALTER TABLE files
+                ADD COLUMN IF NOT EXISTS storage_key varchar,
+                ADD COLUMN IF NOT EXISTS size bigint",
+        )
+        .await?;
+
+        let moved = move_blobs(
+            db,
+            storage,
+            FILES_PREFIX,
+            "SELECT id, data FROM files WHERE storage_key IS NULL ORDER BY id LIMIT 1",
+            "UPDATE files SET storage_key = $1, size = octet_length(data) WHERE id = $2",
+        )
+        .await?;
+
+        db.execute_unprepared(
+            "ALTER TABLE files
+                DROP COLUMN data,
+                ALTER COLUMN storage_key SET NOT NULL,
+                ALTER COLUMN size SET NOT NULL",
+        )
+        .await?;
+
+        info!(count = moved, "Moved files out of the database");
+    }
+
+    if column_exists(db, "users", "profile_picture").await? {
+        db.execute_unprepared(
+            "ALTER TABLE users ADD COLUMN IF NOT EXISTS profile_picture_key varchar",
+        )
+        .await?;
+
+        let moved = move_blobs(
+            db,
+            storage,
+            PROFILE_PICTURES_PREFIX,
+            "SELECT id, profile_picture AS data FROM users
+                WHERE profile_picture IS NOT NULL AND profile_picture_key IS NULL
+                ORDER BY id LIMIT 1",
+            "UPDATE users SET profile_picture_key = $1 WHERE id = $2",
+        )
+        .await?;
+
+        db.execute_unprepared("ALTER TABLE users DROP COLUMN profile_picture")
+            .await?;
+
+        info!(count = moved, "Moved profile pictures out of the database");
+    }
+
+    if column_exists(db, "exports", "data").await? {
+        db.execute_unprepared(
+            "ALTER TABLE exports
+                ADD COLUMN IF NOT EXISTS storage_key varchar,
+                ADD COLUMN IF NOT EXISTS size bigint",
+        )
+        .await?;
+
+        let moved = move_blobs(
+            db,
+            storage,
+            EXPORTS_PREFIX,
+            "SELECT id, data FROM exports
+                WHERE data IS NOT NULL AND storage_key IS NULL
+                ORDER BY id LIMIT 1",
+            "UPDATE exports SET storage_key = $1, size = octet_length(data) WHERE id = $2",
+        )
+        .await?;
+
+        db.execute_unprepared("ALTER TABLE exports DROP COLUMN data")
+            .await?;
+
+        info!(count = moved, "Moved export archives out of the database");
+    }
+
+    Ok(())
+}
+
+/// Records the MIME type of blobs stored before it was kept, by sniffing their first bytes, and the
+/// size of profile pictures. Runs before schema sync, which couldn't add `files.content_type` as a
+/// non-null column to existing rows.
+pub async fn backfill_content_types(db: &DatabaseConnection, storage: &dyn Storage) -> Result<()> {
+    if column_exists(db, "files", "id").await?
+        && column_nullable(db, "files", "content_type").await? != Some(false)
+    {
+        db.execute_unprepared("ALTER TABLE files ADD COLUMN IF NOT EXISTS content_type varchar")
+            .await?;
+
+        let mut filled = 0;
+        loop {
+            let rows = db
+                .query_all_raw(Statement::from_string(
+                    DbBackend::Postgres,
+                    "SELECT id, storage_key FROM files WHERE content_type IS NULL ORDER BY id LIMIT 100",
+                ))
+                .await?;
+            if rows.is_empty() {
+                break;
+            }
+
+            for row in rows {
+                let id: i32 = row.try_get("", "id")?;
+                let key: String = row.try_get("", "storage_key")?;
+
+                let head = storage
+                    .get(&key, None)
+                    .await?
+                    .next()
+                    .await
+                    .transpose()?
+                    .unwrap_or_default();
+
+                db.execute_raw(Statement::from_sql_and_values(
+                    DbBackend::Postgres,
+                    "UPDATE files SET content_type = $1 WHERE id = $2",
+                    [sniff_content_type(&head).into(), id.into()],
+                ))
+                .await?;
+
+                filled += 1;
+            }
+        }
+
+        db.execute_unprepared("ALTER TABLE files ALTER COLUMN content_type SET NOT NULL")
+            .await?;
+
+        info!(count = filled, "Recorded content types of files");
+    }
+
+    if column_exists(db, "users", "profile_picture_key").await? {
+        if !column_exists(db, "users", "profile_picture_content_type").await? {
+            db.execute_unprepared(
+                "ALTER TABLE users
+                    ADD COLUMN IF NOT EXISTS profile_picture_content_type varchar,
+                    ADD COLUMN IF NOT EXISTS profile_picture_size bigint",
+            )
+            .await?;
+        }
+
+        let rows = db
+            .query_all_raw(Statement::from_string(
+                DbBackend::Postgres,
+                "SELECT id, profile_picture_key FROM users
+                    WHERE profile_picture_key IS NOT NULL AND profile_picture_content_type IS NULL",
+            ))
+            .await?;
+
+        for row in &rows {
+            let id: i32 = row.try_get("", "id")?;
+            let key: String = row.try_get("", "profile_picture_key")?;
+
+            // Profile pictures are small enough to read whole
+            let picture = storage.read(&key).await?;
+
+            db.execute_raw(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                "UPDATE users SET profile_picture_content_type = $1, profile_picture_size = $2
+                    WHERE id = $3",
+                [
+                    sniff_content_type(&picture).into(),
+                    (picture.len() as i64).into(),
+                    id.into(),
+                ],
+            ))
+            .await?;
+        }
+
+        if !rows.is_empty() {
+            info!(
+                count = rows.len(),
+                "Recorded content types of profile pictures"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Uploads one row at a time, so large tables don't have to fit in memory. `update` gets the new
+/// key and the row ID.
+async fn move_blobs(
+    db: &DatabaseConnection,
+    storage: &dyn Storage,
+    prefix: &str,
+    select: &str,
+    update: &str,
+) -> Result<u64> {
+    let mut moved = 0;
+
+    while let Some(row) = db
+        .query_one_raw(Statement::from_string(DbBackend::Postgres, select))
+        .await?
+    {
+        let id: i32 = row.try_get("", "id")?;
+        let data: Vec<u8> = row.try_get("", "data")?;
+
+        let key = new_key(prefix);
+        storage.put(&key, data.into()).await?;
+
+        db.execute_raw(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            update,
+            [key.into(), id.into()],
+        ))
+        .await?;
+
+        moved += 1;
+    }
+
+    Ok(moved)
+}
@@ -0,0 +1,106 @@
+use std::ops::Range;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use color_eyre::Result;
+use futures::{StreamExt, TryStreamExt};
+use object_store::{
+    GetOptions, GetRange, ObjectStore, PutPayload, WriteMultipart,
+    aws::{AmazonS3, AmazonS3Builder},
+    path::Path,
+};
+use tracing::warn;
+
+use super::{BlobStream, Storage};
+
+/// Keeps blobs in an S3 compatible bucket, such as AWS S3 or MinIO
+pub struct S3Storage {
+    store: AmazonS3,
+}
+
+impl S3Storage {
+    /// Credentials missing here are taken from the usual `AWS_*` environment variables
+    pub fn new(
+        bucket: &str,
+        region: &str,
+        endpoint: Option<&str>,
+        access_key_id: Option<&str>,
+        secret_access_key: Option<&str>,
+    ) -> Result<Self> {
+        let mut builder = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .with_region(region);
+
+        if let Some(endpoint) = endpoint {
+            // Self-hosted servers like MinIO are often reached over plain HTTP
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+        if let Some(access_key_id) = access_key_id {
+            builder = builder.with_access_key_id(access_key_id);
+        }
+        if let Some(secret_access_key) = secret_access_key {
+            builder = builder.with_secret_access_key(secret_access_key);
+        }
+
+        Ok(Self {
+            store: builder.build()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, data: Bytes) -> Result<()> {
+        self.store
+            .put(&Path::from(key), PutPayload::from_bytes(data))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn put_stream(&self, key: &str, mut data: BlobStream) -> Result<()> {
+        let upload = self.store.put_multipart(&Path::from(key)).await?;
+        let mut writer = WriteMultipart::new(upload);
+
+        let written: Result<()> = async {
+            while let Some(chunk) = data.try_next().await? {
+                // Bounds how many parts are buffered while they upload
+                writer.wait_for_capacity(4).await?;
+                writer.put(chunk);
+            }
+            Ok(())
+        }
+        .await;
+
+        if let Err(error) = written {
+            if let Err(abort_error) = writer.abort().await {
+                warn!(key, error = ?abort_error, "Failed to abort upload");
+            }
+            return Err(error);
+        }
+        writer.finish().await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<BlobStream> {
+        let options = GetOptions {
+            range: range.map(GetRange::Bounded),
+            ..Default::default()
+        };
+        let result = self.store.get_opts(&Path::from(key), options).await?;
+
+        Ok(result.into_stream().map_err(std::io::Error::other).boxed())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match self.store.delete(&Path::from(key)).await {
+            Err(error) if !matches!(error, object_store::Error::NotFound { .. }) => {
+                Err(error.into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -1 +1,15 @@
+pub mod attachments;
+pub mod blocks;
+pub mod cleanup;
+pub mod downloads;
+pub mod export;
+pub mod pagination;
+pub mod passwords;
+pub mod revisions;
+pub mod search;
+pub mod sessions;
+pub mod tags;
 pub mod tokens;
+pub mod two_factor;
+pub mod usernames;
+pub mod verification;
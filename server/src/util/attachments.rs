@@ -0,0 +1,58 @@
+use color_eyre::{Result, eyre::eyre};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QuerySelect,
+    sea_query::OnConflict,
+};
+
+use crate::{
+    entity::{file, note_files},
+    errors::{AxumError, AxumResult},
+};
+
+/// Checks that `user_id` owns every file in `file_ids`, so they can be attached to a note. Files
+/// that don't exist or belong to someone else are rejected with 404 alike.
+pub async fn own_files(
+    db: &impl ConnectionTrait,
+    user_id: i32,
+    file_ids: &[i32],
+) -> AxumResult<Vec<i32>> {
+    let mut file_ids = file_ids.to_vec();
+    file_ids.sort_unstable();
+    file_ids.dedup();
+
+    let owned: Vec<i32> = file::Entity::find()
+        .select_only()
+        .column(file::Column::Id)
+        .filter(file::Column::Id.is_in(file_ids.clone()))
+        .filter(file::Column::UserId.eq(user_id))
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    if owned.len() != file_ids.len() {
+        return Err(AxumError::not_found(eyre!("File not found")));
+    }
+
+    Ok(file_ids)
+}
+
+/// Links files to a note, skipping the ones already attached
+pub async fn attach_files(db: &impl ConnectionTrait, note_id: i32, file_ids: &[i32]) -> Result<()> {
+    if file_ids.is_empty() {
+        return Ok(());
+    }
+
+    note_files::Entity::insert_many(file_ids.iter().map(|file_id| note_files::ActiveModel {
+        note_id: Set(note_id),
+        file_id: Set(*file_id),
+    }))
+    .on_conflict(
+        OnConflict::columns([note_files::Column::NoteId, note_files::Column::FileId])
+            .do_nothing()
+            .to_owned(),
+    )
+    .exec_without_returning(db)
+    .await?;
+
+    Ok(())
+}
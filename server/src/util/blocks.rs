@@ -0,0 +1,47 @@
+use color_eyre::Result;
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, EntityTrait, PaginatorTrait, QueryFilter, QuerySelect,
+    QueryTrait,
+};
+
+use crate::entity::{
+    block::{self, BlockKind},
+    note,
+};
+
+/// Leaves out notes by authors `user_id` muted or blocked, and by authors who blocked them
+pub fn visible_authors(user_id: i32) -> Condition {
+    let restricted = block::Entity::find()
+        .select_only()
+        .column(block::Column::TargetId)
+        .filter(block::Column::UserId.eq(user_id))
+        .into_query();
+
+    Condition::all()
+        .add(note::Column::UserId.not_in_subquery(restricted))
+        .add(not_blocked_by(user_id))
+}
+
+/// Leaves out notes by authors who blocked `user_id`
+pub fn not_blocked_by(user_id: i32) -> Condition {
+    let blocked_by = block::Entity::find()
+        .select_only()
+        .column(block::Column::UserId)
+        .filter(block::Column::TargetId.eq(user_id))
+        .filter(block::Column::Kind.eq(BlockKind::Block))
+        .into_query();
+
+    Condition::all().add(note::Column::UserId.not_in_subquery(blocked_by))
+}
+
+/// Whether `user_id` blocked `target_id`
+pub async fn has_blocked(db: &impl ConnectionTrait, user_id: i32, target_id: i32) -> Result<bool> {
+    let count = block::Entity::find()
+        .filter(block::Column::UserId.eq(user_id))
+        .filter(block::Column::TargetId.eq(target_id))
+        .filter(block::Column::Kind.eq(BlockKind::Block))
+        .count(db)
+        .await?;
+
+    Ok(count > 0)
+}
@@ -0,0 +1,119 @@
+use color_eyre::Result;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QuerySelect, QueryTrait, sea_query::Expr,
+};
+
+use crate::{
+    entity::{export, file, note, note_files, note_revision, user},
+    util::{passwords::hash_password, tokens::generate_token},
+};
+
+/// Owner of public notes kept after their author deleted their account
+pub const DELETED_USERNAME: &str = "[deleted]";
+
+/// Makes sure the placeholder owner for anonymised notes exists, so nobody can register its name
+pub async fn ensure_deleted_user(db: &impl ConnectionTrait) -> Result<user::Model> {
+    if let Some(user) = user::Entity::find_by_username(DELETED_USERNAME)
+        .one(db)
+        .await?
+    {
+        return Ok(user);
+    }
+
+    let user = user::ActiveModel {
+        username: Set(DELETED_USERNAME.to_string()),
+        email: Set("deleted@localhost.invalid".to_string()),
+        // Nobody knows this password, so the account can't be logged into
+        password: Set(hash_password(&generate_token())?),
+        created_at: Set(chrono::Utc::now().naive_utc()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(user)
+}
+
+/// Removes a user and all their data. With `keep_public_notes`, their public notes and the files
+/// attached to them are handed to the [`DELETED_USERNAME`] placeholder instead. Returns the storage
+/// keys of the blobs nothing refers to anymore, to be removed from storage once the transaction
+/// commits.
+///
+/// Everything else that belongs to the user or their notes goes with them through the foreign
+/// keys, which all cascade.
+pub async fn delete_account(
+    db: &impl ConnectionTrait,
+    user_id: i32,
+    keep_public_notes: bool,
+) -> Result<Vec<String>> {
+    if keep_public_notes {
+        // Notes in the trash go even when they're public
+        let kept_notes: Vec<i32> = note::Entity::find()
+            .select_only()
+            .column(note::Column::Id)
+            .filter(note::Column::UserId.eq(user_id))
+            .filter(note::Column::Public.eq(true))
+            .filter(note::Column::DeletedAt.is_null())
+            .into_tuple()
+            .all(db)
+            .await?;
+
+        if !kept_notes.is_empty() {
+            let placeholder = ensure_deleted_user(db).await?;
+
+            note::Entity::update_many()
+                .col_expr(note::Column::UserId, Expr::value(placeholder.id))
+                .filter(note::Column::Id.is_in(kept_notes.clone()))
+                .exec(db)
+                .await?;
+            note_revision::Entity::update_many()
+                .col_expr(note_revision::Column::AuthorId, Expr::value(placeholder.id))
+                .filter(note_revision::Column::NoteId.is_in(kept_notes.clone()))
+                .exec(db)
+                .await?;
+
+            let kept_files = note_files::Entity::find()
+                .select_only()
+                .column(note_files::Column::FileId)
+                .filter(note_files::Column::NoteId.is_in(kept_notes))
+                .into_query();
+
+            file::Entity::update_many()
+                .col_expr(file::Column::UserId, Expr::value(placeholder.id))
+                .filter(file::Column::Id.in_subquery(kept_files))
+                .filter(file::Column::UserId.eq(user_id))
+                .exec(db)
+                .await?;
+        }
+    }
+
+    let mut storage_keys: Vec<String> = file::Entity::find()
+        .select_only()
+        .column(file::Column::StorageKey)
+        .filter(file::Column::UserId.eq(user_id))
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let profile_picture_key: Option<Option<String>> = user::Entity::find_by_id(user_id)
+        .select_only()
+        .column(user::Column::ProfilePictureKey)
+        .into_tuple()
+        .one(db)
+        .await?;
+    storage_keys.extend(profile_picture_key.flatten());
+
+    let export_keys: Vec<Option<String>> = export::Entity::find()
+        .select_only()
+        .column(export::Column::StorageKey)
+        .filter(export::Column::UserId.eq(user_id))
+        .into_tuple()
+        .all(db)
+        .await?;
+    storage_keys.extend(export_keys.into_iter().flatten());
+
+    user::Entity::delete_by_id(user_id).exec(db).await?;
+
+    Ok(storage_keys)
+}
@@ -0,0 +1,267 @@
+use std::ops::Range;
+
+use axum::{
+    body::Body,
+    response::{IntoResponse, Response},
+};
+use http::{HeaderMap, HeaderValue, StatusCode, header};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use sanitize_filename::sanitize;
+
+use crate::{errors::AxumResult, storage::Storage, util::tokens::hash_token};
+
+/// A stored blob to send to a client
+pub struct Download<'a> {
+    pub storage_key: &'a str,
+    pub size: u64,
+    pub content_type: &'a str,
+
+    /// Suggested name for saving it, sent in `Content-Disposition`
+    pub filename: Option<&'a str>,
+
+    /// Have browsers save it rather than display it, only used with a `filename`
+    pub attachment: bool,
+
+    pub cache_control: &'static str,
+}
+
+/// A single byte range from a `Range` header
+enum ByteRange {
+    /// `bytes=start-` or `bytes=start-end`, with `end` inclusive
+    From { start: u64, end: Option<u64> },
+
+    /// `bytes=-length`, the last `length` bytes
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Multiple ranges and anything malformed are `None`, those requests get the whole blob
+    fn parse(value: &str) -> Option<Self> {
+        let spec = value.strip_prefix("bytes=")?.trim();
+        if spec.contains(',') {
+            return None;
+        }
+
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+
+        if start.is_empty() {
+            return Some(Self::Suffix(end.parse().ok()?));
+        }
+
+        let start = start.parse().ok()?;
+        let end = match end {
+            "" => None,
+            end => Some(end.parse().ok().filter(|end| *end >= start)?),
+        };
+
+        Some(Self::From { start, end })
+    }
+
+    /// The requested bytes of a blob of `size`, `None` when it has none of them
+    fn within(&self, size: u64) -> Option<Range<u64>> {
+        let range = match *self {
+            Self::From { start, end } => {
+                start..end.map_or(size, |end| end.saturating_add(1).min(size))
+            }
+            Self::Suffix(length) => size.saturating_sub(length)..size,
+        };
+
+        (range.start < range.end).then_some(range)
+    }
+}
+
+/// Streams a blob with its type, length and caching headers. A matching `If-None-Match` gets 304
+/// and a single byte `Range` gets 206.
+pub async fn serve_download(
+    storage: &dyn Storage,
+    request: &HeaderMap,
+    download: Download<'_>,
+) -> AxumResult<Response> {
+    // Blobs are never changed in place, new contents get a new key
+    let etag = format!("\"{}\"", &hash_token(download.storage_key)[..32]);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ETAG, HeaderValue::from_str(&etag)?);
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(download.cache_control),
+    );
+
+    let if_none_match = request
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok());
+    if if_none_match.is_some_and(|value| etag_matches(value, &etag)) {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(download.content_type)?,
+    );
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Some(filename) = download.filename {
+        headers.insert(
+            header::CONTENT_DISPOSITION,
+            content_disposition(filename, download.attachment)?,
+        );
+    }
+
+    // `If-Range` asks for the whole blob when the client's copy is outdated
+    let if_range = request
+        .get(header::IF_RANGE)
+        .and_then(|value| value.to_str().ok());
+    let requested = request
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| if_range.is_none_or(|value| value == etag))
+        .and_then(ByteRange::parse);
+
+    let (status, range) = match requested {
+        None => (StatusCode::OK, None),
+        Some(requested) => {
+            let Some(range) = requested.within(download.size) else {
+                headers.insert(
+                    header::CONTENT_RANGE,
+                    HeaderValue::from_str(&format!("bytes */{}", download.size))?,
+                );
+                return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+            };
+
+            headers.insert(
+                header::CONTENT_RANGE,
+                HeaderValue::from_str(&format!(
+                    "bytes {}-{}/{}",
+                    range.start,
+                    range.end - 1,
+                    download.size
+                ))?,
+            );
+            (StatusCode::PARTIAL_CONTENT, Some(range))
+        }
+    };
+
+    let length = range
+        .as_ref()
+        .map_or(download.size, |range| range.end - range.start);
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+
+    let contents = storage.get(download.storage_key, range).await?;
+
+    Ok((status, headers, Body::from_stream(contents)).into_response())
+}
+
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .any(|tag| tag.trim().trim_start_matches("W/") == etag)
+}
+
+/// `inline` unless `attachment`, so images still display, with an ASCII name for clients that
+/// don't read `filename*`
+fn content_disposition(filename: &str, attachment: bool) -> AxumResult<HeaderValue> {
+    let filename = sanitize(filename);
+
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c == ' ' || c.is_ascii_graphic() => c,
+            _ => '_',
+        })
+        .collect();
+    let encoded = utf8_percent_encode(&filename, NON_ALPHANUMERIC);
+
+    let disposition = if attachment { "attachment" } else { "inline" };
+
+    Ok(HeaderValue::from_str(&format!(
+        "{disposition}; filename=\"{fallback}\"; filename*=UTF-8''{encoded}"
+    ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ByteRange, content_disposition, etag_matches};
+
+    fn within(header: &str, size: u64) -> Option<(u64, u64)> {
+        ByteRange::parse(header)?
+            .within(size)
+            .map(|range| (range.start, range.end))
+    }
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(within("bytes=0-9", 100), Some((0, 10)));
+        assert_eq!(within("bytes=90-", 100), Some((90, 100)));
+        assert_eq!(within("bytes= 5 - 5 ", 100), Some((5, 6)));
+        assert_eq!(within("bytes=-10", 100), Some((90, 100)));
+    }
+
+    #[test]
+    fn clamps_ranges_to_the_blob() {
+        assert_eq!(within("bytes=50-1000", 100), Some((50, 100)));
+        assert_eq!(within("bytes=-1000", 100), Some((0, 100)));
+        assert_eq!(within("bytes=0-18446744073709551615", 100), Some((0, 100)));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges_have_no_bytes() {
+        assert!(ByteRange::parse("bytes=100-").is_some());
+        assert_eq!(within("bytes=100-", 100), None);
+        assert_eq!(within("bytes=-0", 100), None);
+        assert_eq!(within("bytes=0-", 0), None);
+    }
+
+    #[test]
+    fn ignores_malformed_and_multiple_ranges() {
+        for header in [
+            "bytes=5-2",
+            "bytes=0-1,5-6",
+            "bytes=-",
+            "bytes=a-b",
+            "bytes=--5",
+            "bytes=0-1-2",
+            "items=0-1",
+            "0-1",
+        ] {
+            assert!(ByteRange::parse(header).is_none(), "{header}");
+        }
+    }
+
+    #[test]
+    fn matches_etags() {
+        let etag = "\"abc\"";
+
+        assert!(etag_matches("\"abc\"", etag));
+        assert!(etag_matches("W/\"abc\"", etag));
+        assert!(etag_matches("\"xyz\", W/\"abc\"", etag));
+        assert!(etag_matches(" * ", etag));
+        assert!(!etag_matches("\"xyz\"", etag));
+        assert!(!etag_matches("abc", etag));
+        assert!(!etag_matches("", etag));
+    }
+
+    #[test]
+    fn encodes_non_ascii_filenames() {
+        let value = content_disposition("Übung 2 – Lösungen.pdf", false).unwrap();
+
+        assert_eq!(
+            value,
+            "inline; filename=\"_bung 2 _ L_sungen.pdf\"; \
+             filename*=UTF-8''%C3%9Cbung%202%20%E2%80%93%20L%C3%B6sungen%2Epdf"
+        );
+    }
+
+    #[test]
+    fn escapes_quotes_and_path_separators() {
+        let value = content_disposition("../\"notes\"\\a.txt", false).unwrap();
+        let value = value.to_str().unwrap();
+
+        assert!(!value.contains('/'), "{value}");
+        assert!(
+            value.starts_with("inline; filename=\"..notesa.txt\""),
+            "{value}"
+        );
+    }
+}
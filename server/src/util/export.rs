@@ -0,0 +1,422 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use color_eyre::{Result, eyre::eyre};
+use futures::{StreamExt, TryStreamExt};
+use sanitize_filename::sanitize;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection,
+    EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+};
+use serde::Serialize;
+use tokio::runtime::Handle;
+use tokio_util::io::ReaderStream;
+use tracing::{error, warn};
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    entity::{
+        block::{self, BlockKind},
+        export::{self, ExportStatus},
+        file, follow, note, question, quiz, save, upvote, user,
+    },
+    mailer::Email,
+    state::AppState,
+    storage::{EXPORTS_PREFIX, Storage, new_key},
+    util::{tags::load_tag_names, tokens::generate_token},
+};
+
+#[derive(Serialize)]
+struct ProfileExport {
+    id: i32,
+    username: String,
+    display_name: Option<String>,
+    bio: Option<String>,
+    school: Option<String>,
+    class: Option<String>,
+    preferred_language: Option<String>,
+    email: String,
+    email_verified_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+    profile_picture: Option<String>,
+}
+
+#[derive(Serialize)]
+struct QuizExport {
+    note_id: i32,
+    questions: Vec<QuestionExport>,
+}
+
+#[derive(Serialize)]
+struct QuestionExport {
+    title: String,
+    answers: Vec<String>,
+    correct: i32,
+}
+
+#[derive(Serialize)]
+struct BookmarkExport {
+    note_id: i32,
+    title: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FollowExport {
+    user_id: i32,
+    username: String,
+}
+
+#[derive(Serialize)]
+struct BlockExport {
+    user_id: i32,
+    username: String,
+    kind: BlockKind,
+}
+
+#[derive(Serialize)]
+struct VoteExport {
+    note_id: i32,
+    is_upvote: bool,
+}
+
+/// Builds the archive for a pending export and stores it, or marks the export as failed.
+/// `download_token` is only known here and to whoever started the export, the database has its
+/// hash.
+pub async fn run_export(state: AppState, export_id: i32, download_token: String) {
+    if let Err(error) = try_run_export(&state, export_id, &download_token).await {
+        error!(error = ?error, export_id, "Failed to build personal data export");
+
+        let export = export::ActiveModel {
+            id: Set(export_id),
+            status: Set(ExportStatus::Failed),
+            finished_at: Set(Some(Utc::now())),
+            ..Default::default()
+        };
+        if let Err(error) = export.update(&state.db).await {
+            error!(error = ?error, export_id, "Failed to mark export as failed");
+        }
+    }
+}
+
+async fn try_run_export(state: &AppState, export_id: i32, download_token: &str) -> Result<()> {
+    let export = export::Entity::find_by_id(export_id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| eyre!("Export not found"))?;
+    let user = user::Entity::find_by_id(export.user_id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| eyre!("User not found"))?;
+
+    // Built on disk rather than in memory, since it holds every file the user uploaded
+    let archive = std::env::temp_dir().join(format!("export-{}.zip", generate_token()));
+    let key = new_key(EXPORTS_PREFIX);
+    let stored = store_archive(state, &user, &archive, &key).await;
+    // Missing when building the archive failed early
+    tokio::fs::remove_file(&archive).await.ok();
+    let size = stored?;
+
+    let now = Utc::now();
+    let expires_at = now + TimeDelta::seconds(state.settings.export.download_lifetime);
+
+    let export = export::ActiveModel {
+        id: Set(export_id),
+        status: Set(ExportStatus::Ready),
+        finished_at: Set(Some(now)),
+        expires_at: Set(Some(expires_at)),
+        storage_key: Set(Some(key.clone())),
+        size: Set(Some(size as i64)),
+        ..Default::default()
+    };
+    // The account may have been deleted in the meantime
+    if let Err(error) = export.update(&state.db).await {
+        state.storage.delete_all(&[key]).await;
+        return Err(error.into());
+    }
+
+    let email = Email {
+        to: user.email,
+        subject: "Your Mathisi data export is ready".to_string(),
+        body: format!(
+            "Hi {},\n\nYour data export is ready. Download it here:\n{}\n\nThe link expires on {} UTC.\n",
+            user.username,
+            download_url(state, download_token),
+            expires_at.format("%Y-%m-%d %H:%M"),
+        ),
+    };
+
+    // The response that started the export had the link too
+    if let Err(error) = state.mailer.send(email).await {
+        warn!(error = ?error, "Failed to send export email");
+    }
+
+    Ok(())
+}
+
+/// Builds the archive at `archive` and uploads it under `key`, returning its size
+async fn store_archive(
+    state: &AppState,
+    user: &user::Model,
+    archive: &Path,
+    key: &str,
+) -> Result<u64> {
+    build_archive(&state.db, state.storage.clone(), user, archive).await?;
+
+    let file = tokio::fs::File::open(archive).await?;
+    let size = file.metadata().await?.len();
+    state
+        .storage
+        .put_stream(key, ReaderStream::new(file).boxed())
+        .await?;
+
+    Ok(size)
+}
+
+/// Keeps a user to one export being built at a time, even when they start two at once. Schema
+/// sync can't create partial indexes.
+pub async fn init_export_indexes(db: &impl ConnectionTrait) -> Result<()> {
+    // Duplicates from before the index would keep it from being created
+    db.execute_unprepared(
+        "UPDATE exports SET status = 'failed', finished_at = now()
+            WHERE status = 'pending' AND id NOT IN (
+                SELECT max(id) FROM exports WHERE status = 'pending' GROUP BY user_id
+            )",
+    )
+    .await?;
+    db.execute_unprepared(
+        r#"CREATE UNIQUE INDEX IF NOT EXISTS "idx-exports-pending-user_id" ON exports (user_id)
+            WHERE status = 'pending'"#,
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub fn download_url(state: &AppState, download_token: &str) -> String {
+    format!(
+        "{}api/exports/{}",
+        state.settings.general.public_url, download_token
+    )
+}
+
+/// Writes the ZIP archive to `path`. Files are streamed from storage one at a time, so only one
+/// chunk of them is in memory at once.
+async fn build_archive(
+    db: &DatabaseConnection,
+    storage: Arc<dyn Storage>,
+    user: &user::Model,
+    path: &Path,
+) -> Result<()> {
+    let notes = note::Entity::find()
+        .filter(note::Column::UserId.eq(user.id))
+        .order_by_asc(note::Column::Id)
+        .all(db)
+        .await?;
+    let note_ids: Vec<i32> = notes.iter().map(|note| note.id).collect();
+    let mut note_tags = load_tag_names(db, &note_ids).await?;
+
+    let files = file::Entity::find()
+        .filter(file::Column::UserId.eq(user.id))
+        .order_by_asc(file::Column::Id)
+        .all(db)
+        .await?;
+
+    let note_quizzes = quiz::Entity::find()
+        .filter(quiz::Column::NoteId.is_in(notes.iter().map(|note| note.id)))
+        .order_by_asc(quiz::Column::NoteId)
+        .all(db)
+        .await?;
+
+    let mut questions = question::Entity::find()
+        .filter(question::Column::QuizId.is_in(note_quizzes.iter().map(|quiz| quiz.id)))
+        .order_by_asc(question::Column::Id)
+        .all(db)
+        .await?;
+
+    let quizzes: Vec<QuizExport> = note_quizzes
+        .into_iter()
+        .map(|quiz| QuizExport {
+            note_id: quiz.note_id,
+            questions: questions
+                .extract_if(.., |question| question.quiz_id == quiz.id)
+                .map(|question| QuestionExport {
+                    title: question.title,
+                    answers: question.answers,
+                    correct: question.correct,
+                })
+                .collect(),
+        })
+        .collect();
+
+    let bookmarks: Vec<BookmarkExport> = save::Entity::find()
+        .filter(save::Column::UserId.eq(user.id))
+        .find_also_related(note::Entity)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|(save, note)| BookmarkExport {
+            note_id: save.note_id,
+            title: note.map(|note| note.title),
+        })
+        .collect();
+
+    let votes: Vec<VoteExport> = upvote::Entity::find()
+        .filter(upvote::Column::UserId.eq(user.id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|vote| VoteExport {
+            note_id: vote.note_id,
+            is_upvote: vote.is_upvote,
+        })
+        .collect();
+
+    let following: Vec<FollowExport> = user::Entity::find()
+        .join(JoinType::InnerJoin, follow::Relation::Followee.def().rev())
+        .filter(follow::Column::FollowerId.eq(user.id))
+        .order_by_asc(follow::Column::CreatedAt)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|followee| FollowExport {
+            user_id: followee.id,
+            username: followee.username,
+        })
+        .collect();
+
+    let block_list = block::Entity::find()
+        .filter(block::Column::UserId.eq(user.id))
+        .order_by_asc(block::Column::CreatedAt)
+        .all(db)
+        .await?;
+    let block_targets: HashMap<i32, String> = user::Entity::find()
+        .select_only()
+        .columns([user::Column::Id, user::Column::Username])
+        .filter(user::Column::Id.is_in(block_list.iter().map(|block| block.target_id)))
+        .into_tuple()
+        .all(db)
+        .await?
+        .into_iter()
+        .collect();
+    let blocks: Vec<BlockExport> = block_list
+        .into_iter()
+        .map(|block| BlockExport {
+            user_id: block.target_id,
+            username: block_targets
+                .get(&block.target_id)
+                .cloned()
+                .unwrap_or_default(),
+            kind: block.kind,
+        })
+        .collect();
+
+    let picture = match &user.profile_picture_key {
+        Some(key) => Some(storage.read(key).await?),
+        None => None,
+    };
+    let profile_picture_name = picture.as_ref().map(|picture| {
+        let extension = infer::get(picture).map_or("bin", |kind| kind.extension());
+        format!("profile_picture.{extension}")
+    });
+
+    let profile = ProfileExport {
+        id: user.id,
+        username: user.username.clone(),
+        display_name: user.display_name.clone(),
+        bio: user.bio.clone(),
+        school: user.school.clone(),
+        class: user.class.clone(),
+        preferred_language: user.preferred_language.clone(),
+        email: user.email.clone(),
+        email_verified_at: user.email_verified_at,
+        created_at: user.created_at,
+        profile_picture: profile_picture_name.clone(),
+    };
+
+    let path = path.to_owned();
+
+    // Compressing is CPU bound, keep it off the async workers
+    tokio::task::spawn_blocking(move || {
+        let runtime = Handle::current();
+        let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+        let options = SimpleFileOptions::default();
+
+        zip.start_file("profile.json", options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&profile)?)?;
+
+        if let (Some(name), Some(picture)) = (profile_picture_name, picture) {
+            zip.start_file(name, options)?;
+            zip.write_all(&picture)?;
+        }
+
+        for note in notes {
+            zip.start_file(
+                format!("notes/{}-{}.md", note.id, archive_name(&note.title)),
+                options,
+            )?;
+            let tags = note_tags.remove(&note.id).unwrap_or_default();
+            zip.write_all(note_markdown(&note, &tags)?.as_bytes())?;
+        }
+
+        for file in files {
+            zip.start_file(
+                format!("files/{}-{}", file.id, archive_name(&file.filename)),
+                options,
+            )?;
+            let mut contents = runtime.block_on(storage.get(&file.storage_key, None))?;
+            while let Some(chunk) = runtime.block_on(contents.try_next())? {
+                zip.write_all(&chunk)?;
+            }
+        }
+
+        zip.start_file("quizzes.json", options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&quizzes)?)?;
+
+        zip.start_file("bookmarks.json", options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&bookmarks)?)?;
+
+        zip.start_file("votes.json", options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&votes)?)?;
+
+        zip.start_file("following.json", options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&following)?)?;
+
+        zip.start_file("blocks.json", options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&blocks)?)?;
+
+        zip.finish()?.into_inner()?.sync_all()?;
+
+        Ok(())
+    })
+    .await?
+}
+
+/// Keeps user-chosen names safe and reasonably short inside the archive
+fn archive_name(name: &str) -> String {
+    let name: String = sanitize(name).chars().take(64).collect();
+
+    if name.is_empty() {
+        "untitled".to_string()
+    } else {
+        name
+    }
+}
+
+fn note_markdown(note: &note::Model, tags: &[String]) -> Result<String> {
+    // JSON strings are valid YAML, which saves escaping titles by hand
+    Ok(format!(
+        "---\nid: {}\ntitle: {}\ntags: {}\npublic: {}\ncreated_at: {}\n---\n\n{}\n",
+        note.id,
+        serde_json::to_string(&note.title)?,
+        serde_json::to_string(tags)?,
+        note.public,
+        note.created_at.to_rfc3339(),
+        note.content,
+    ))
+}
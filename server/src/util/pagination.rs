@@ -0,0 +1,238 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::eyre;
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+    Select,
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use validator::Validate;
+
+use crate::errors::{AxumError, AxumResult};
+
+/// Page size when the client doesn't ask for one
+pub const DEFAULT_PAGE_SIZE: u64 = 20;
+
+#[derive(Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// Items per page, 20 by default
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<u64>,
+
+    /// `next_cursor` of the previous page, leave out for the first page
+    #[validate(length(max = 64))]
+    pub cursor: Option<String>,
+}
+
+/// One page of results, newest first
+pub struct Page<T> {
+    pub items: Vec<T>,
+
+    /// Pass as `cursor` to get the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Where a page ends. Clients only ever see it encoded.
+struct Cursor {
+    created_at: DateTime<Utc>,
+    id: i32,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!(
+            "{}:{}",
+            self.created_at.timestamp_micros(),
+            self.id
+        ))
+    }
+
+    fn decode(cursor: &str) -> AxumResult<Self> {
+        let invalid = || AxumError::bad_request(eyre!("Invalid cursor"));
+
+        let decoded = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let (created_at, id) = decoded.split_once(':').ok_or_else(invalid)?;
+
+        Ok(Cursor {
+            created_at: created_at
+                .parse()
+                .ok()
+                .and_then(DateTime::from_timestamp_micros)
+                .ok_or_else(invalid)?,
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Loads one page of `select` ordered by `created_at` then `id`, newest first. `key` reads the
+/// same two values from a row.
+pub async fn paginate<E>(
+    db: &DatabaseConnection,
+    select: Select<E>,
+    (created_at, id): (E::Column, E::Column),
+    page: &PageQuery,
+    key: impl Fn(&E::Model) -> (DateTime<Utc>, i32),
+) -> AxumResult<Page<E::Model>>
+where
+    E: EntityTrait,
+{
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+
+    let mut select = select
+        .order_by_desc(created_at)
+        .order_by_desc(id)
+        // One more than needed tells whether there's a next page
+        .limit(limit + 1);
+
+    if let Some(cursor) = &page.cursor {
+        let cursor = Cursor::decode(cursor)?;
+        select = select.filter(
+            Condition::any().add(created_at.lt(cursor.created_at)).add(
+                Condition::all()
+                    .add(created_at.eq(cursor.created_at))
+                    .add(id.lt(cursor.id)),
+            ),
+        );
+    }
+
+    let mut items = select.all(db).await?;
+
+    let next_cursor = if items.len() as u64 > limit {
+        items.truncate(limit as usize);
+        items.last().map(|last| {
+            let (created_at, id) = key(last);
+            Cursor { created_at, id }.encode()
+        })
+    } else {
+        None
+    };
+
+    Ok(Page { items, next_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+    use chrono::{DateTime, TimeZone, Utc};
+    use sea_orm::{DatabaseBackend, EntityTrait, MockDatabase};
+
+    use super::{Cursor, PageQuery, paginate};
+    use crate::entity::note_revision;
+
+    fn revision(id: i32, created_at: DateTime<Utc>) -> note_revision::Model {
+        note_revision::Model {
+            id,
+            note_id: 1,
+            author_id: 1,
+            created_at,
+            title: String::new(),
+            content: String::new(),
+            public: false,
+        }
+    }
+
+    fn page(limit: u64, cursor: Option<String>) -> PageQuery {
+        PageQuery {
+            limit: Some(limit),
+            cursor,
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let created_at = Utc.timestamp_micros(1_700_000_000_123_456).unwrap();
+        let cursor = Cursor::decode(&Cursor { created_at, id: 42 }.encode()).unwrap();
+
+        assert_eq!(cursor.created_at, created_at);
+        assert_eq!(cursor.id, 42);
+    }
+
+    #[test]
+    fn rejects_tampered_cursors() {
+        let encode = |raw: &[u8]| URL_SAFE_NO_PAD.encode(raw);
+
+        for cursor in [
+            String::new(),
+            "not base64!".to_string(),
+            encode(b"1700000000123456"),
+            encode(b"1700000000123456:"),
+            encode(b"1700000000123456:abc"),
+            encode(b"1700000000123456:99999999999"),
+            encode(b"yesterday:42"),
+            encode(b"99999999999999999999:42"),
+            encode(b"\xff\xfe:42"),
+        ] {
+            assert!(Cursor::decode(&cursor).is_err(), "{cursor}");
+        }
+    }
+
+    #[tokio::test]
+    async fn returns_a_cursor_only_when_there_are_more_rows() {
+        let now = Utc::now();
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([
+                vec![revision(3, now), revision(2, now), revision(1, now)],
+                vec![revision(1, now)],
+            ])
+            .into_connection();
+        let columns = (note_revision::Column::CreatedAt, note_revision::Column::Id);
+        let key = |revision: &note_revision::Model| (revision.created_at, revision.id);
+
+        let first = paginate(
+            &db,
+            note_revision::Entity::find(),
+            columns,
+            &page(2, None),
+            key,
+        )
+        .await
+        .unwrap();
+        assert_eq!(first.items.len(), 2);
+        let next_cursor = first.next_cursor.unwrap();
+        let cursor = Cursor::decode(&next_cursor).unwrap();
+        assert_eq!(cursor.id, 2);
+
+        let second = paginate(
+            &db,
+            note_revision::Entity::find(),
+            columns,
+            &page(2, Some(next_cursor)),
+            key,
+        )
+        .await
+        .unwrap();
+        assert_eq!(second.items.len(), 1);
+        assert!(second.next_cursor.is_none());
+
+        let log = db.into_transaction_log();
+        assert_eq!(log.len(), 2);
+        let sql = log[1].statements()[0].sql.clone();
+        assert!(
+            sql.contains(
+                r#"WHERE "note_revisions"."created_at" < $1 OR ("note_revisions"."created_at" = $2 AND "note_revisions"."id" < $3)"#
+            ),
+            "{sql}"
+        );
+        assert!(sql.contains("LIMIT $4"), "{sql}");
+    }
+
+    #[tokio::test]
+    async fn rejects_a_tampered_cursor_without_querying() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres).into_connection();
+
+        let result = paginate(
+            &db,
+            note_revision::Entity::find(),
+            (note_revision::Column::CreatedAt, note_revision::Column::Id),
+            &page(2, Some("bm9wZQ".to_string())),
+            |revision| (revision.created_at, revision.id),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(db.into_transaction_log().is_empty());
+    }
+}
@@ -0,0 +1,24 @@
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use color_eyre::eyre::{Result, eyre};
+use password_hash::{PasswordHasher, SaltString, rand_core::OsRng};
+
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    let password_hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| eyre!("Failed to hash password: {}", e))?;
+
+    Ok(password_hash.to_string())
+}
+
+/// Returns `false` both for a wrong password and for a malformed stored hash
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return false;
+    };
+
+    Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok()
+}
@@ -0,0 +1,57 @@
+use chrono::Utc;
+use color_eyre::Result;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, PaginatorTrait,
+    QueryFilter,
+};
+
+use crate::entity::{note, note_revision};
+
+/// Stores the current state of `note` as a new revision by `author_id`
+pub async fn record_revision(
+    db: &impl ConnectionTrait,
+    note: &note::Model,
+    author_id: i32,
+) -> Result<note_revision::Model> {
+    let revision = note_revision::ActiveModel {
+        note_id: Set(note.id),
+        author_id: Set(author_id),
+        created_at: Set(Utc::now()),
+        title: Set(note.title.clone()),
+        content: Set(note.content.clone()),
+        public: Set(note.public),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(revision)
+}
+
+/// Stores `note` as its first revision, dated to when it was created, unless it has revisions
+/// already. Notes from before revisions were kept would otherwise lose their original on edit.
+///
+/// Call it after updating the note in the same transaction. The row lock that takes keeps two
+/// edits at once from both adding the first revision.
+pub async fn ensure_initial_revision(db: &impl ConnectionTrait, note: &note::Model) -> Result<()> {
+    let revisions = note_revision::Entity::find()
+        .filter(note_revision::Column::NoteId.eq(note.id))
+        .count(db)
+        .await?;
+
+    if revisions == 0 {
+        note_revision::ActiveModel {
+            note_id: Set(note.id),
+            author_id: Set(note.user_id),
+            created_at: Set(note.created_at),
+            title: Set(note.title.clone()),
+            content: Set(note.content.clone()),
+            public: Set(note.public),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+
+    Ok(())
+}
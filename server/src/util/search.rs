@@ -0,0 +1,50 @@
+use color_eyre::Result;
+use sea_orm::{ConnectionTrait, sea_query::Expr};
+
+/// Text search configuration used for stemming and stop words. The generated columns are built
+/// with it, so changing it means dropping them first.
+pub const TEXT_SEARCH_CONFIG: &str = "english";
+
+/// Generated `tsvector` column over a note's title and content
+pub const NOTE_SEARCH_COLUMN: &str = "search_vector";
+
+/// Generated `tsvector` column over a file's OCR text
+pub const FILE_SEARCH_COLUMN: &str = "ocr_vector";
+
+/// Adds the generated search columns and their GIN indexes. Schema sync can't create generated
+/// columns, so they aren't part of the entities.
+pub async fn init_search_columns(db: &impl ConnectionTrait) -> Result<()> {
+    db.execute_unprepared(&format!(
+        "ALTER TABLE notes ADD COLUMN IF NOT EXISTS {NOTE_SEARCH_COLUMN} tsvector GENERATED ALWAYS AS (
+            setweight(to_tsvector('{TEXT_SEARCH_CONFIG}', title), 'A')
+            || setweight(to_tsvector('{TEXT_SEARCH_CONFIG}', content), 'B')
+        ) STORED"
+    ))
+    .await?;
+    db.execute_unprepared(&format!(
+        r#"CREATE INDEX IF NOT EXISTS "idx-notes-{NOTE_SEARCH_COLUMN}" ON notes USING GIN ({NOTE_SEARCH_COLUMN})"#
+    ))
+    .await?;
+
+    db.execute_unprepared(&format!(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS {FILE_SEARCH_COLUMN} tsvector GENERATED ALWAYS AS (
+            to_tsvector('{TEXT_SEARCH_CONFIG}', coalesce(ocr, ''))
+        ) STORED"
+    ))
+    .await?;
+    db.execute_unprepared(&format!(
+        r#"CREATE INDEX IF NOT EXISTS "idx-files-{FILE_SEARCH_COLUMN}" ON files USING GIN ({FILE_SEARCH_COLUMN})"#
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Parses what a user typed into the search box. Supports `"quoted phrases"`, `or` and
+/// `-excluded` words, and never fails on bad syntax.
+pub fn to_tsquery(query: &str) -> Expr {
+    Expr::cust_with_values(
+        format!("websearch_to_tsquery('{TEXT_SEARCH_CONFIG}', $1)"),
+        [query.to_string()],
+    )
+}
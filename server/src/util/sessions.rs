@@ -0,0 +1,61 @@
+use chrono::{NaiveDateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, EntityTrait, QueryFilter,
+    sea_query::Expr,
+};
+
+use crate::{
+    entity::{token, user},
+    errors::AxumResult,
+    state::AppState,
+    util::tokens::{TokenPair, hash_token},
+};
+
+/// Issues a new token pair for `user_id`, e.g. after a successful login
+///
+/// Logging in during the grace period of an account deletion cancels it.
+pub async fn start_session(state: &AppState, user_id: i32) -> AxumResult<TokenPair> {
+    let now = Utc::now();
+
+    user::Entity::update_many()
+        .col_expr(
+            user::Column::DeletionScheduledAt,
+            Expr::value(Option::<NaiveDateTime>::None),
+        )
+        .filter(user::Column::Id.eq(user_id))
+        .filter(user::Column::DeletionScheduledAt.is_not_null())
+        .exec(&state.db)
+        .await?;
+
+    // Drop sessions that can no longer be used or refreshed
+    token::Entity::delete_many()
+        .filter(token::Column::UserId.eq(user_id))
+        .filter(
+            Condition::any()
+                .add(token::Column::RefreshExpiresAt.lt(now))
+                .add(
+                    Condition::all()
+                        .add(token::Column::RefreshExpiresAt.is_null())
+                        .add(token::Column::ExpiresAt.lt(now)),
+                ),
+        )
+        .exec(&state.db)
+        .await?;
+
+    let pair = TokenPair::generate(&state.settings.auth);
+
+    let token_entry = token::ActiveModel {
+        user_id: Set(user_id),
+        token_hash: Set(hash_token(&pair.access_token)),
+        created_at: Set(now),
+        expires_at: Set(Some(pair.expires_at)),
+        last_used_at: Set(now),
+        refresh_token_hash: Set(Some(hash_token(&pair.refresh_token))),
+        refresh_expires_at: Set(Some(pair.refresh_expires_at)),
+        ..Default::default()
+    };
+
+    token_entry.insert(&state.db).await?;
+
+    Ok(pair)
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use color_eyre::{Result, eyre::eyre};
+use sea_orm::{
+    ActiveValue::Set,
+    ColumnTrait, Condition, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+    QueryTrait,
+    sea_query::{ExprTrait, OnConflict},
+};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use crate::{
+    entity::{note, note_tags, tag},
+    errors::{AxumError, AxumResult},
+};
+
+/// Longest tag name, in characters
+pub const MAX_TAG_LENGTH: u64 = 32;
+
+/// Most tags a single note can have
+pub const MAX_TAGS_PER_NOTE: u64 = 10;
+
+/// Brings a tag name to the form it's stored in: lowercase, without a leading `#`, with spaces
+/// and underscores turned into dashes. `None` when nothing valid is left.
+pub fn normalize_tag(name: &str) -> Option<String> {
+    let name = name.trim().trim_start_matches('#').to_lowercase();
+    let name = name
+        .split(|c: char| c.is_whitespace() || c == '_' || c == '-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    let valid = !name.is_empty()
+        && name.chars().count() as u64 <= MAX_TAG_LENGTH
+        && name.chars().all(|c| c.is_alphanumeric() || c == '-');
+
+    valid.then_some(name)
+}
+
+/// Normalises and deduplicates user-provided tag names, rejecting invalid ones with 400
+pub fn normalize_tags<'a>(names: impl IntoIterator<Item = &'a str>) -> AxumResult<Vec<String>> {
+    let mut normalized = Vec::new();
+
+    for name in names {
+        let tag = normalize_tag(name)
+            .ok_or_else(|| AxumError::bad_request(eyre!("Invalid tag: {name}")))?;
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+
+    Ok(normalized)
+}
+
+/// Finds the tags with the given normalised names, creating the ones that don't exist yet
+pub async fn get_or_create_tags(
+    db: &impl ConnectionTrait,
+    names: &[String],
+) -> Result<Vec<tag::Model>> {
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Concurrent requests may create the same tag, so conflicts are expected
+    tag::Entity::insert_many(names.iter().map(|name| tag::ActiveModel {
+        name: Set(name.clone()),
+        ..Default::default()
+    }))
+    .on_conflict(
+        OnConflict::column(tag::Column::Name)
+            .do_nothing()
+            .to_owned(),
+    )
+    .exec_without_returning(db)
+    .await?;
+
+    let mut tags = tag::Entity::find()
+        .filter(tag::Column::Name.is_in(names))
+        .all(db)
+        .await?;
+    tags.sort_by_key(|tag| names.iter().position(|name| *name == tag.name));
+
+    Ok(tags)
+}
+
+/// Replaces a note's tags with the given normalised names
+pub async fn set_note_tags(
+    db: &impl ConnectionTrait,
+    note_id: i32,
+    names: &[String],
+) -> Result<()> {
+    let tags = get_or_create_tags(db, names).await?;
+
+    note_tags::Entity::delete_many()
+        .filter(note_tags::Column::NoteId.eq(note_id))
+        .exec(db)
+        .await?;
+
+    if !tags.is_empty() {
+        note_tags::Entity::insert_many(tags.iter().map(|tag| note_tags::ActiveModel {
+            note_id: Set(note_id),
+            tag_id: Set(tag.id),
+        }))
+        .exec_without_returning(db)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Tag names of each note, alphabetically. Notes without tags are left out.
+pub async fn load_tag_names(
+    db: &impl ConnectionTrait,
+    note_ids: &[i32],
+) -> Result<HashMap<i32, Vec<String>>> {
+    let rows: Vec<(i32, String)> = note_tags::Entity::find()
+        .select_only()
+        .column(note_tags::Column::NoteId)
+        .column(tag::Column::Name)
+        .inner_join(tag::Entity)
+        .filter(note_tags::Column::NoteId.is_in(note_ids.iter().copied()))
+        .order_by_asc(tag::Column::Name)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+    for (note_id, name) in rows {
+        tags.entry(note_id).or_default().push(name);
+    }
+
+    Ok(tags)
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TagMatch {
+    /// Notes with at least one of the tags
+    #[default]
+    Any,
+    /// Notes with every one of the tags
+    All,
+}
+
+#[derive(Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct TagFilter {
+    /// Comma-separated tag names to filter by
+    #[validate(length(max = 512))]
+    pub tags: Option<String>,
+
+    /// Whether notes need any or all of `tags`
+    #[serde(default)]
+    pub tags_match: TagMatch,
+}
+
+impl TagFilter {
+    /// Condition on notes matching the filter, `None` when no tags were given
+    pub fn condition(&self) -> AxumResult<Option<Condition>> {
+        let Some(tags) = &self.tags else {
+            return Ok(None);
+        };
+
+        let names = normalize_tags(tags.split(',').filter(|name| !name.trim().is_empty()))?;
+        if names.is_empty() {
+            return Ok(None);
+        }
+
+        let mut tagged = note_tags::Entity::find()
+            .select_only()
+            .column(note_tags::Column::NoteId)
+            .inner_join(tag::Entity)
+            .filter(tag::Column::Name.is_in(names.clone()));
+
+        if let TagMatch::All = self.tags_match {
+            tagged = tagged
+                .group_by(note_tags::Column::NoteId)
+                .having(note_tags::Column::TagId.count().eq(names.len() as i64));
+        }
+
+        Ok(Some(
+            Condition::all().add(note::Column::Id.in_subquery(tagged.into_query())),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_TAG_LENGTH, normalize_tag, normalize_tags};
+
+    #[test]
+    fn normalizes_tags() {
+        for (name, expected) in [
+            ("algebra", "algebra"),
+            ("  #Algebra ", "algebra"),
+            ("##Linear Algebra", "linear-algebra"),
+            ("linear_algebra", "linear-algebra"),
+            ("linear -  _algebra", "linear-algebra"),
+            ("-linear-algebra-", "linear-algebra"),
+            ("Ableitung", "ableitung"),
+            ("ÜBUNG 2", "übung-2"),
+        ] {
+            assert_eq!(normalize_tag(name).as_deref(), Some(expected), "{name}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_tags() {
+        for name in ["", "   ", "#", "#_ -", "c++", "a/b", "tag#2", "emoji🙂"] {
+            assert_eq!(normalize_tag(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn limits_length_in_characters() {
+        let longest = "ä".repeat(MAX_TAG_LENGTH as usize);
+        assert_eq!(normalize_tag(&longest), Some(longest.clone()));
+        assert_eq!(normalize_tag(&format!("{longest}a")), None);
+    }
+
+    #[test]
+    fn deduplicates_after_normalizing() {
+        let tags = normalize_tags(["Algebra", "#algebra", "linear algebra", "linear_algebra"]);
+        assert_eq!(tags.unwrap(), ["algebra", "linear-algebra"]);
+
+        assert!(normalize_tags(["algebra", "c++"]).is_err());
+    }
+}
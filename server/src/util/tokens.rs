@@ -1,6 +1,9 @@
+use chrono::{DateTime, TimeDelta, Utc};
 use rand::{Rng, distr::Alphanumeric};
 use sha2::{Digest, Sha256};
 
+use crate::settings::Auth;
+
 pub fn generate_token() -> String {
     let rng = rand::rngs::ThreadRng::default();
 
@@ -16,3 +19,24 @@ pub fn generate_token() -> String {
 pub fn hash_token(token: &str) -> String {
     format!("{:x}", Sha256::digest(token))
 }
+
+/// A freshly minted access token together with the refresh token that rotates it
+pub struct TokenPair {
+    pub access_token: String,
+    pub expires_at: DateTime<Utc>,
+    pub refresh_token: String,
+    pub refresh_expires_at: DateTime<Utc>,
+}
+
+impl TokenPair {
+    pub fn generate(settings: &Auth) -> Self {
+        let now = Utc::now();
+
+        Self {
+            access_token: generate_token(),
+            expires_at: now + TimeDelta::seconds(settings.access_token_lifetime),
+            refresh_token: generate_token(),
+            refresh_expires_at: now + TimeDelta::seconds(settings.refresh_token_lifetime),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::Utc;
+use color_eyre::{Result, eyre::eyre};
+use rand::{Rng, distr::Alphanumeric};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait, EntityTrait, QueryFilter,
+};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::{
+    entity::{recovery_code, user},
+    state::AppState,
+    util::tokens::hash_token,
+};
+
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Generates a new base32 encoded TOTP secret
+pub fn generate_totp_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+fn totp(secret: &str, issuer: &str, username: &str) -> Result<TOTP> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|error| eyre!("Invalid TOTP secret: {error}"))?;
+
+    // The otpauth label uses `:` to separate the issuer from the account
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        Some(issuer.replace(':', "")),
+        username.replace(':', ""),
+    )?;
+
+    Ok(totp)
+}
+
+/// The `otpauth://` URI authenticator apps import, usually shown as a QR code
+pub fn otpauth_uri(secret: &str, issuer: &str, username: &str) -> Result<String> {
+    Ok(totp(secret, issuer, username)?.get_url())
+}
+
+/// Returns the time step `code` was generated for, if it's valid now
+fn totp_step(secret: &str, code: &str) -> Result<Option<i64>> {
+    // Issuer and account only matter for the URI
+    let totp = totp(secret, "", "")?;
+    let now = Utc::now().timestamp() as u64;
+    let current = now / totp.step;
+
+    let step = [current - 1, current, current + 1]
+        .into_iter()
+        .find(|step| totp.generate(step * totp.step) == code);
+
+    Ok(step.map(|step| step as i64))
+}
+
+/// Checks a TOTP code for `user`, refusing codes that were already used
+pub async fn verify_totp(state: &AppState, user: &user::Model, code: &str) -> Result<bool> {
+    let Some(secret) = &user.totp_secret else {
+        return Ok(false);
+    };
+
+    let Some(step) = totp_step(secret, code)? else {
+        return Ok(false);
+    };
+
+    // Checked in the update itself, so the same code sent twice at once only gets in once
+    let result = user::Entity::update_many()
+        .set(user::ActiveModel {
+            totp_last_step: Set(Some(step)),
+            ..Default::default()
+        })
+        .filter(user::Column::Id.eq(user.id))
+        .filter(
+            Condition::any()
+                .add(user::Column::TotpLastStep.is_null())
+                .add(user::Column::TotpLastStep.lt(step)),
+        )
+        .exec(&state.db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Accepts either a TOTP code or one of the user's recovery codes, which is used up
+pub async fn verify_second_factor(
+    state: &AppState,
+    user: &user::Model,
+    code: &str,
+) -> Result<bool> {
+    let code = code.trim();
+
+    if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
+        return verify_totp(state, user, code).await;
+    }
+
+    // Whoever deletes the code gets to use it
+    let result = recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user.id))
+        .filter(recovery_code::Column::CodeHash.eq(hash_recovery_code(code)))
+        .exec(&state.db)
+        .await?;
+
+    Ok(result.rows_affected > 0)
+}
+
+/// Ignores case and separators, so codes can be typed however they were written down
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    hash_token(&normalized)
+}
+
+/// Replaces the user's recovery codes, returning the new ones in plain text
+pub async fn regenerate_recovery_codes(
+    db: &impl ConnectionTrait,
+    user_id: i32,
+) -> Result<Vec<String>> {
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    let now = Utc::now();
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = rand::rng()
+                .sample_iter(&Alphanumeric)
+                .take(10)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect();
+
+    let models = codes.iter().map(|code| recovery_code::ActiveModel {
+        user_id: Set(user_id),
+        code_hash: Set(hash_recovery_code(code)),
+        created_at: Set(now),
+        ..Default::default()
+    });
+    recovery_code::Entity::insert_many(models).exec(db).await?;
+
+    Ok(codes)
+}
@@ -0,0 +1,77 @@
+use chrono::{TimeDelta, Utc};
+use color_eyre::Result;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, PaginatorTrait,
+    QueryFilter,
+};
+
+use validator::ValidationError;
+
+use crate::entity::{user, username_reservation};
+
+/// Longest username, in characters
+pub const MAX_USERNAME_LENGTH: usize = 128;
+
+/// Rules every username has to follow, whether the user picked it or it was made up for them
+pub fn validate_username(username: &str) -> Result<(), ValidationError> {
+    let length = username.chars().count();
+
+    if (1..=MAX_USERNAME_LENGTH).contains(&length) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("length"))
+    }
+}
+
+/// Whether `username` is free for `user_id`, or for a new account when `None`. Names reserved
+/// after a rename are only free for the user who gave them up.
+pub async fn is_username_available(
+    db: &impl ConnectionTrait,
+    username: &str,
+    user_id: Option<i32>,
+) -> Result<bool> {
+    let taken = user::Entity::find()
+        .filter(user::Column::Username.eq(username))
+        .count(db)
+        .await?
+        > 0;
+    if taken {
+        return Ok(false);
+    }
+
+    let reservation = username_reservation::Entity::find()
+        .filter(username_reservation::Column::Username.eq(username))
+        .filter(username_reservation::Column::ExpiresAt.gt(Utc::now()))
+        .one(db)
+        .await?;
+
+    Ok(reservation.is_none_or(|reservation| Some(reservation.user_id) == user_id))
+}
+
+/// Reserves the user's current name for them for `reservation_period` seconds, before they're
+/// renamed to `new_username`
+pub async fn reserve_old_username(
+    db: &impl ConnectionTrait,
+    user: &user::Model,
+    new_username: &str,
+    reservation_period: i64,
+) -> Result<()> {
+    // Clears the user's own reservation when they take a name back, and expired leftovers
+    username_reservation::Entity::delete_many()
+        .filter(
+            username_reservation::Column::Username.is_in([new_username, user.username.as_str()]),
+        )
+        .exec(db)
+        .await?;
+
+    username_reservation::ActiveModel {
+        username: Set(user.username.clone()),
+        user_id: Set(user.id),
+        expires_at: Set(Utc::now() + TimeDelta::seconds(reservation_period)),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use chrono::{TimeDelta, Utc};
+use color_eyre::{Result, eyre::eyre};
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
+
+use crate::{
+    entity::{email_verification, user},
+    errors::{AxumError, AxumResult},
+    mailer::Email,
+    state::AppState,
+    util::tokens::{generate_token, hash_token},
+};
+
+/// Replaces any pending verification for `user` and emails a fresh link to `email`
+pub async fn send_verification_email(
+    state: &AppState,
+    user: &user::Model,
+    email: &str,
+) -> Result<()> {
+    email_verification::Entity::delete_many()
+        .filter(email_verification::Column::UserId.eq(user.id))
+        .exec(&state.db)
+        .await?;
+
+    let token = generate_token();
+    let now = Utc::now();
+    let lifetime = TimeDelta::seconds(state.settings.auth.email_verification_lifetime);
+
+    let verification = email_verification::ActiveModel {
+        user_id: Set(user.id),
+        token_hash: Set(hash_token(&token)),
+        email: Set(email.to_string()),
+        created_at: Set(now),
+        expires_at: Set(now + lifetime),
+        ..Default::default()
+    };
+
+    verification.insert(&state.db).await?;
+
+    state
+        .mailer
+        .send(Email {
+            to: email.to_string(),
+            subject: "Verify your Mathisi email".to_string(),
+            body: format!(
+                "Hi {},\n\nConfirm your email address with this link:\n{}verify-email?token={}\n\nThe link expires in {} days.\n",
+                user.username,
+                state.settings.general.public_url,
+                token,
+                lifetime.num_days()
+            ),
+        })
+        .await
+}
+
+/// Rejects unverified accounts when the corresponding setting is enabled
+pub fn require_verified(user: &user::Model, required: bool) -> AxumResult<()> {
+    if required && user.email_verified_at.is_none() {
+        return Err(
+            AxumError::forbidden(eyre!("Verify your email address first"))
+                .with_code("email_not_verified"),
+        );
+    }
+
+    Ok(())
+}